@@ -5,9 +5,16 @@ use camino::Utf8PathBuf as PathBuf;
 use once_cell::sync::Lazy;
 use std::collections::HashSet;
 use std::env;
-use std::fs::{self, File};
-use std::io::Write;
-use std::process::Command;
+use std::fs;
+use std::io::{self, Write};
+use std::process::{Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+#[path = "build/helpers.rs"]
+mod helpers;
+
+use helpers::FFmpegLinkMode;
 
 /// All the libs that FFmpeg has
 static LIBS: &'static [&'static str] = &[
@@ -22,145 +29,11 @@ static LIBS: &'static [&'static str] = &[
 
 /// Whitelist of the headers we want to generate bindings
 static HEADERS: Lazy<Vec<PathBuf>> = Lazy::new(|| {
-    [
-        "libavcodec/ac3_parser.h",
-        "libavcodec/adts_parser.h",
-        "libavcodec/avcodec.h",
-        "libavcodec/avdct.h",
-        "libavcodec/avfft.h",
-        "libavcodec/bsf.h",
-        "libavcodec/codec.h",
-        "libavcodec/codec_desc.h",
-        "libavcodec/codec_id.h",
-        "libavcodec/codec_par.h",
-        // "libavcodec/d3d11va.h",
-        "libavcodec/defs.h",
-        "libavcodec/dirac.h",
-        "libavcodec/dv_profile.h",
-        // "libavcodec/dxva2.h",
-        "libavcodec/jni.h",
-        "libavcodec/mediacodec.h",
-        "libavcodec/packet.h",
-        // "libavcodec/qsv.h",
-        // "libavcodec/vdpau.h",
-        "libavcodec/version.h",
-        "libavcodec/version_major.h",
-        // "libavcodec/videotoolbox.h",
-        "libavcodec/vorbis_parser.h",
-        // "libavcodec/xvmc.h",
-        "libavdevice/avdevice.h",
-        "libavdevice/version.h",
-        "libavdevice/version_major.h",
-        "libavfilter/avfilter.h",
-        "libavfilter/buffersink.h",
-        "libavfilter/buffersrc.h",
-        "libavfilter/version.h",
-        "libavfilter/version_major.h",
-        "libavformat/avformat.h",
-        "libavformat/avio.h",
-        "libavformat/version.h",
-        "libavformat/version_major.h",
-        "libavutil/adler32.h",
-        "libavutil/aes.h",
-        "libavutil/aes_ctr.h",
-        "libavutil/ambient_viewing_environment.h",
-        "libavutil/attributes.h",
-        "libavutil/audio_fifo.h",
-        "libavutil/avassert.h",
-        "libavutil/avconfig.h",
-        "libavutil/avstring.h",
-        "libavutil/avutil.h",
-        "libavutil/base64.h",
-        "libavutil/blowfish.h",
-        "libavutil/bprint.h",
-        "libavutil/bswap.h",
-        "libavutil/buffer.h",
-        "libavutil/camellia.h",
-        "libavutil/cast5.h",
-        "libavutil/channel_layout.h",
-        "libavutil/common.h",
-        "libavutil/cpu.h",
-        "libavutil/crc.h",
-        "libavutil/csp.h",
-        "libavutil/des.h",
-        "libavutil/detection_bbox.h",
-        "libavutil/dict.h",
-        "libavutil/display.h",
-        "libavutil/dovi_meta.h",
-        "libavutil/downmix_info.h",
-        "libavutil/encryption_info.h",
-        "libavutil/error.h",
-        "libavutil/eval.h",
-        "libavutil/executor.h",
-        "libavutil/ffversion.h",
-        "libavutil/fifo.h",
-        "libavutil/file.h",
-        "libavutil/film_grain_params.h",
-        "libavutil/frame.h",
-        "libavutil/hash.h",
-        "libavutil/hdr_dynamic_metadata.h",
-        "libavutil/hdr_dynamic_vivid_metadata.h",
-        "libavutil/hmac.h",
-        "libavutil/hwcontext.h",
-        // "libavutil/hwcontext_cuda.h",
-        // "libavutil/hwcontext_d3d11va.h",
-        // "libavutil/hwcontext_drm.h",
-        // "libavutil/hwcontext_dxva2.h",
-        // "libavutil/hwcontext_mediacodec.h",
-        // "libavutil/hwcontext_opencl.h",
-        // "libavutil/hwcontext_qsv.h",
-        // "libavutil/hwcontext_vaapi.h",
-        // "libavutil/hwcontext_vdpau.h",
-        // "libavutil/hwcontext_videotoolbox.h",
-        // "libavutil/hwcontext_vulkan.h",
-        "libavutil/imgutils.h",
-        "libavutil/intfloat.h",
-        "libavutil/intreadwrite.h",
-        "libavutil/lfg.h",
-        "libavutil/log.h",
-        "libavutil/lzo.h",
-        "libavutil/macros.h",
-        "libavutil/mastering_display_metadata.h",
-        "libavutil/mathematics.h",
-        "libavutil/md5.h",
-        "libavutil/mem.h",
-        "libavutil/motion_vector.h",
-        "libavutil/murmur3.h",
-        "libavutil/opt.h",
-        "libavutil/parseutils.h",
-        "libavutil/pixdesc.h",
-        "libavutil/pixelutils.h",
-        "libavutil/pixfmt.h",
-        "libavutil/random_seed.h",
-        "libavutil/rational.h",
-        "libavutil/rc4.h",
-        "libavutil/replaygain.h",
-        "libavutil/ripemd.h",
-        "libavutil/samplefmt.h",
-        "libavutil/sha.h",
-        "libavutil/sha512.h",
-        "libavutil/spherical.h",
-        "libavutil/stereo3d.h",
-        "libavutil/tea.h",
-        "libavutil/threadmessage.h",
-        "libavutil/time.h",
-        "libavutil/timecode.h",
-        "libavutil/timestamp.h",
-        "libavutil/tree.h",
-        "libavutil/twofish.h",
-        "libavutil/tx.h",
-        "libavutil/uuid.h",
-        "libavutil/version.h",
-        "libavutil/video_enc_params.h",
-        "libavutil/video_hint.h",
-        "libavutil/xtea.h",
-        "libswresample/swresample.h",
-        "libswresample/version.h",
-        "libswresample/version_major.h",
-        "libswscale/swscale.h",
-        "libswscale/version.h",
-        "libswscale/version_major.h",
-    ]
+    helpers::headers(
+        cfg!(feature = "full-bindings"),
+        cfg!(feature = "crypto"),
+        cfg!(feature = "hash"),
+    )
     .into_iter()
     .map(|x| Path::new(x).into_iter().collect())
     .collect()
@@ -190,44 +63,99 @@ impl callbacks::ParseCallbacks for FilterCargoCallbacks {
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
-enum FFmpegLinkMode {
-    Static,
-    Dynamic,
+enum FFmpegLicense {
+    Gpl,
+    Lgpl,
 }
 
-#[cfg(not(target_os = "windows"))]
-impl FFmpegLinkMode {
-    fn is_static(&self) -> bool {
-        self == &Self::Static
+impl From<String> for FFmpegLicense {
+    fn from(value: String) -> Self {
+        match &*value {
+            "gpl" => FFmpegLicense::Gpl,
+            "lgpl" => FFmpegLicense::Lgpl,
+            _ => panic!("Invalid FFMPEG_LICENSE value, expected [gpl,lgpl]"),
+        }
     }
 }
 
-impl From<String> for FFmpegLinkMode {
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FFmpegThreads {
+    Auto,
+    On,
+    Off,
+}
+
+impl From<String> for FFmpegThreads {
     fn from(value: String) -> Self {
         match &*value {
-            "static" => FFmpegLinkMode::Static,
-            "dynamic" => FFmpegLinkMode::Dynamic,
-            _ => panic!("Invalid FFMPEG_LINK_MODE value, expected [static,dynamic]"),
+            "auto" => FFmpegThreads::Auto,
+            "on" => FFmpegThreads::On,
+            "off" => FFmpegThreads::Off,
+            _ => panic!("Invalid FFMPEG_THREADS value, expected [auto,on,off]"),
         }
     }
 }
 
-impl std::fmt::Display for FFmpegLinkMode {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        match self {
-            FFmpegLinkMode::Static => write!(f, "static"),
-            FFmpegLinkMode::Dynamic => write!(f, "dylib"),
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum FFmpegAsm {
+    On,
+    Off,
+    NoNeon,
+}
+
+impl From<String> for FFmpegAsm {
+    fn from(value: String) -> Self {
+        match &*value {
+            "on" => FFmpegAsm::On,
+            "off" => FFmpegAsm::Off,
+            "no-neon" => FFmpegAsm::NoNeon,
+            _ => panic!("Invalid FFMPEG_ASM value, expected [on,off,no-neon]"),
         }
     }
 }
 
-fn generate_bindings(ffmpeg_include_dir: &Path, headers: &[PathBuf]) -> Bindings {
-    if !Path::new(ffmpeg_include_dir).exists() {
-        panic!(
-            "FFmpeg include dir: `{:?}` doesn't exits",
-            ffmpeg_include_dir
-        );
-    }
+/// External libraries which FFmpeg's configure only allows together with `--enable-gpl`
+static GPL_ONLY_COMPONENTS: &[&str] = &[
+    "avisynth",
+    "frei0r",
+    "libcdio",
+    "libdavs2",
+    "librubberband",
+    "libvidstab",
+    "libx264",
+    "libx265",
+    "libxavs",
+    "libxavs2",
+    "libxvid",
+    "postproc",
+];
+
+/// Write a header including all the existing `headers`, so that bindgen
+/// parses a single input file.
+fn write_combined_header(
+    ffmpeg_include_dir: &Path,
+    headers: &[PathBuf],
+    out_dir: &Path,
+) -> PathBuf {
+    let includes = headers
+        .iter()
+        .filter(|header| {
+            let path = ffmpeg_include_dir.join(header);
+            let exists = path.exists();
+            if !exists {
+                eprintln!("Header path `{:?}` not found.", path);
+            }
+            exists
+        })
+        .map(|header| format!("#include <{header}>\n"))
+        .collect::<String>();
+    let combined_header_path = out_dir.join("all_headers.h");
+    fs::write(&combined_header_path, includes).expect("Cannot write the combined header.");
+    combined_header_path
+}
+
+/// The bindgen builder with the options of the bindings, without the headers
+fn bindings_builder(ffmpeg_include_dir: &Path, rust_target: RustTarget, verbose: bool) -> bindgen::Builder {
     // Because of the strange `FP_*` in `math.h` https://github.com/rust-lang/rust-bindgen/issues/687
     let filter_callback = FilterCargoCallbacks::new(
         [
@@ -241,35 +169,114 @@ fn generate_bindings(ffmpeg_include_dir: &Path, headers: &[PathBuf]) -> Bindings
         .collect(),
     );
 
+    let builder = bindgen::builder();
+    // Refer to `core` instead of `std` types for `no_std` crates
+    let builder = if cfg!(feature = "no_std") {
+        builder.use_core().ctypes_prefix("::core::ffi")
+    } else {
+        builder
+    };
+    builder
+        // Force impl Debug if possible(for `AVCodecParameters`).
+        // bindgen can't do this selectively, so `no_debug_impls`
+        // drops the manual impls for all the types.
+        .impl_debug(!cfg!(feature = "no_debug_impls"))
+        .rust_target(rust_target)
+        .parse_callbacks(Box::new(filter_callback))
+        // Add clang path, for `#include` header finding in bindgen process.
+        .clang_arg(format!("-I{}", ffmpeg_include_dir))
+        // Print the include search paths and the clang invocation
+        .clang_args(verbose.then_some("-v"))
+        // Workaround: https://github.com/rust-lang/rust-bindgen/issues/2159
+        .blocklist_type("__mingw_ldbl_type_t")
+        // 128-bit floats (`long double` on aarch64) have no Rust
+        // counterpart, bindgen maps them to `u128` which breaks
+        // `impl_debug` and the layout tests.
+        .blocklist_type("__float128")
+        .blocklist_type("_Float128")
+        // The types containing a `long double` only keep its size and
+        // alignment, rather than the `u128` fields bindgen makes of it
+        .opaque_type("max_align_t")
+        .opaque_type("_Float64x")
+        // Stop bindgen from prefixing enums
+        .prepend_enum_name(false)
+}
+
+fn generate_bindings(
+    ffmpeg_include_dir: &Path,
+    headers: &[PathBuf],
+    out_dir: &Path,
+    rust_target: RustTarget,
+    verbose: bool,
+    check_combined_header: bool,
+) -> Bindings {
+    if !Path::new(ffmpeg_include_dir).exists() {
+        panic!(
+            "FFmpeg include dir: `{:?}` doesn't exits",
+            ffmpeg_include_dir
+        );
+    }
+
+    if verbose {
+        println!(
+            "cargo:warning=Generating bindings for {} headers from {ffmpeg_include_dir}",
+            headers.len()
+        );
+    }
+
     // Bindgen on all avaiable headers
-    headers
-        .iter()
+    let combined_header_path = write_combined_header(ffmpeg_include_dir, headers, out_dir);
+    let started_at = Instant::now();
+    let bindings = bindings_builder(ffmpeg_include_dir, rust_target, verbose)
+        .header(combined_header_path.as_str())
+        .generate()
+        .expect("Binding generation failed.");
+    let elapsed = started_at.elapsed();
+    if verbose {
+        println!("cargo:warning=Bindings generated in {:.1}s", elapsed.as_secs_f64());
+    }
+    if check_combined_header {
+        check_combined_header_bindings(ffmpeg_include_dir, headers, rust_target, &bindings, elapsed);
+    }
+    bindings
+}
+
+/// Generates the bindings passing every header to bindgen separately, and
+/// checks that they have the same symbols as the ones of `all_headers.h`.
+/// Reports the generation time of both.
+fn check_combined_header_bindings(
+    ffmpeg_include_dir: &Path,
+    headers: &[PathBuf],
+    rust_target: RustTarget,
+    bindings: &Bindings,
+    elapsed: Duration,
+) {
+    let started_at = Instant::now();
+    let per_header_bindings = headers.iter()
         .map(|header| ffmpeg_include_dir.join(header))
-        .filter(|path| {
-            let exists = Path::new(&path).exists();
-            if !exists {
-                eprintln!("Header path `{:?}` not found.", path);
-            }
-            exists
-        })
+        .filter(|header_path| header_path.exists())
         .fold(
-            {
-                bindgen::builder()
-                    // Force impl Debug if possible(for `AVCodecParameters`)
-                    .impl_debug(true)
-                    .rust_target(RustTarget::stable(68, 0).ok().unwrap())
-                    .parse_callbacks(Box::new(filter_callback))
-                    // Add clang path, for `#include` header finding in bindgen process.
-                    .clang_arg(format!("-I{}", ffmpeg_include_dir))
-                    // Workaround: https://github.com/rust-lang/rust-bindgen/issues/2159
-                    .blocklist_type("__mingw_ldbl_type_t")
-                    // Stop bindgen from prefixing enums
-                    .prepend_enum_name(false)
-            },
-            |builder, header| builder.header(header),
+            bindings_builder(ffmpeg_include_dir, rust_target, false),
+            |builder, header_path| builder.header(header_path.as_str()),
         )
         .generate()
-        .expect("Binding generation failed.")
+        .expect("Binding generation from the separate headers failed.");
+    let per_header_elapsed = started_at.elapsed();
+    println!(
+        "cargo:warning=Bindings generated in {:.1}s from all_headers.h, in {:.1}s from the separate headers",
+        elapsed.as_secs_f64(),
+        per_header_elapsed.as_secs_f64(),
+    );
+
+    let symbols = helpers::binding_symbols(&bindings.to_string());
+    let per_header_symbols = helpers::binding_symbols(&per_header_bindings.to_string());
+    if symbols != per_header_symbols {
+        for symbol in symbols.symmetric_difference(&per_header_symbols) {
+            let source = if symbols.contains(symbol) { "all_headers.h" } else { "the separate headers" };
+            println!("cargo:warning={symbol} is only bound from {source}");
+        }
+        panic!("The bindings of all_headers.h differ from the ones of the separate headers, see the warnings above");
+    }
 }
 
 #[allow(dead_code)]
@@ -278,35 +285,346 @@ pub struct EnvVars {
     docs_rs: Option<String>,
     out_dir: PathBuf,
     num_jobs: String,
+    ffmpeg_install_dir: Option<PathBuf>,
     ffmpeg_configuration: Vec<String>,
+    ffmpeg_configure_cache: bool,
+    ffmpeg_configuration_strict: bool,
     ffmpeg_link_mode: FFmpegLinkMode,
+    ffmpeg_license: FFmpegLicense,
+    ffmpeg_threads: FFmpegThreads,
+    ffmpeg_asm: FFmpegAsm,
+    ffmpeg_cpu: Option<String>,
+    ffmpeg_target_os: Option<String>,
+    ffmpeg_pic: bool,
     ffmpeg_rockchip_mpp: bool,
+    ffmpeg_verbose: bool,
+    ffmpeg_build_progress: bool,
+    ffmpeg_check_combined_header: bool,
+    ffmpeg_rust_target: RustTarget,
+    ffmpeg_extra_libs: Vec<String>,
+    pkg_config: Option<String>,
+    rockchip_rga_meson_opts: Vec<String>,
+    meson_c_args: Vec<String>,
+    meson_c_link_args: Vec<String>,
+    meson_pkg_config_libdir: Option<String>,
+    rockchip_mpp_cmake_opts: Vec<String>,
+    rockchip_mpp_prebuilt: Option<PrebuiltMpp>,
 }
 
 impl EnvVars {
     fn init() -> Self {
         println!("cargo:rerun-if-env-changed=DOCS_RS");
         println!("cargo:rerun-if-env-changed=OUT_DIR");
+        println!("cargo:rerun-if-env-changed=FFMPEG_INSTALL_DIR");
         println!("cargo:rerun-if-env-changed=FFMPEG_CONFIGURATION");
+        println!("cargo:rerun-if-env-changed=FFMPEG_CONFIGURATION_FILE");
+        println!("cargo:rerun-if-env-changed=FFMPEG_CONFIGURE_CACHE");
+        println!("cargo:rerun-if-env-changed=FFMPEG_CONFIGURATION_STRICT");
         println!("cargo:rerun-if-env-changed=FFMPEG_LINK_MODE");
+        println!("cargo:rerun-if-env-changed=FFMPEG_LICENSE");
+        println!("cargo:rerun-if-env-changed=FFMPEG_THREADS");
+        println!("cargo:rerun-if-env-changed=FFMPEG_ASM");
+        println!("cargo:rerun-if-env-changed=FFMPEG_CPU");
+        println!("cargo:rerun-if-env-changed=FFMPEG_TARGET_OS");
+        println!("cargo:rerun-if-env-changed=FFMPEG_PIC");
         println!("cargo:rerun-if-env-changed=FFMPEG_ROCKCHIP_MPP");
+        println!("cargo:rerun-if-env-changed=FFMPEG_VERBOSE");
+        println!("cargo:rerun-if-env-changed=FFMPEG_BUILD_PROGRESS");
+        println!("cargo:rerun-if-env-changed=FFMPEG_CHECK_COMBINED_HEADER");
+        println!("cargo:rerun-if-env-changed=FFMPEG_RUST_TARGET");
+        println!("cargo:rerun-if-env-changed=FFMPEG_EXTRA_LIBS");
+        println!("cargo:rerun-if-env-changed=ROCKCHIP_RGA_MESON_OPTS");
+        println!("cargo:rerun-if-env-changed=MESON_C_ARGS");
+        println!("cargo:rerun-if-env-changed=MESON_C_LINK_ARGS");
+        println!("cargo:rerun-if-env-changed=MESON_PKG_CONFIG_LIBDIR");
+        println!("cargo:rerun-if-env-changed=ROCKCHIP_MPP_CMAKE_OPTS");
+        println!("cargo:rerun-if-env-changed=ROCKCHIP_MPP_LIB_DIR");
+        println!("cargo:rerun-if-env-changed=ROCKCHIP_MPP_INCLUDE_DIR");
+        let target = env::var("TARGET").expect("TARGET env var");
+        let host = env::var("HOST").expect("HOST env var");
+        let ffmpeg_install_dir = ffmpeg_install_dir_from_env();
         Self {
-            target: env::var("TARGET").expect("TARGET env var"),
+            pkg_config: pkg_config_from_env(&target, &host),
+            target,
             docs_rs: env::var("DOCS_RS").ok(),
             out_dir: remove_verbatim(env::var("OUT_DIR").expect("OUT_DIR env var")),
-            num_jobs: env::var("NUM_JOBS").expect("NUM_JOBS env var"),
-            ffmpeg_configuration: env::var("FFMPEG_CONFIGURATION").expect("FFMPEG_CONFIGURATION env var")
-                .split(' ')
-                .filter(|v| !v.is_empty())
-                .map(String::from)
-                .collect(),
+            // NUM_JOBS is only set when the build script is driven by Cargo
+            num_jobs: helpers::num_jobs(env::var("NUM_JOBS").ok()),
+            // Nothing is configured when linking against a pre-built install
+            ffmpeg_configuration: if ffmpeg_install_dir.is_none() {
+                ffmpeg_configuration_from_env()
+            } else {
+                vec!()
+            },
+            ffmpeg_install_dir,
+            ffmpeg_configure_cache: env::var("FFMPEG_CONFIGURE_CACHE")
+                .map(|v| v.trim().parse().unwrap_or(true)).unwrap_or(true),
+            ffmpeg_configuration_strict: env::var("FFMPEG_CONFIGURATION_STRICT")
+                .map(|v| v.trim().parse().unwrap_or(false)).unwrap_or(false),
             ffmpeg_link_mode: env::var("FFMPEG_LINK_MODE").ok()
-                .map(Into::into)
+                .map(|v| v.parse().unwrap_or_else(|e| panic!("{e}")))
                 .unwrap_or(FFmpegLinkMode::Static),
+            ffmpeg_license: env::var("FFMPEG_LICENSE").ok()
+                .map(Into::into)
+                .unwrap_or(FFmpegLicense::Gpl),
+            ffmpeg_threads: env::var("FFMPEG_THREADS").ok()
+                .map(Into::into)
+                .unwrap_or(FFmpegThreads::Auto),
+            ffmpeg_asm: env::var("FFMPEG_ASM").ok()
+                .map(Into::into)
+                .unwrap_or(FFmpegAsm::On),
+            ffmpeg_cpu: env::var("FFMPEG_CPU").ok()
+                .map(|cpu| {
+                    let cpu = cpu.trim().to_string();
+                    assert!(!cpu.is_empty(), "FFMPEG_CPU env var must not be empty");
+                    cpu
+                }),
+            ffmpeg_target_os: env::var("FFMPEG_TARGET_OS").ok()
+                .map(|target_os| {
+                    let target_os = target_os.trim().to_string();
+                    assert!(!target_os.is_empty(), "FFMPEG_TARGET_OS env var must not be empty");
+                    target_os
+                }),
+            ffmpeg_pic: env::var("FFMPEG_PIC")
+                .map(|v| v.trim().parse().unwrap_or(true)).unwrap_or(true),
             ffmpeg_rockchip_mpp: env::var("FFMPEG_ROCKCHIP_MPP")
+                .map(|v| v.trim().parse().unwrap_or(false))
+                .unwrap_or(cfg!(feature = "default-rkmpp")),
+            ffmpeg_verbose: env::var("FFMPEG_VERBOSE")
+                .map(|v| v.trim().parse().unwrap_or(false)).unwrap_or(false),
+            ffmpeg_build_progress: env::var("FFMPEG_BUILD_PROGRESS")
+                .map(|v| v.trim().parse().unwrap_or(false)).unwrap_or(false),
+            ffmpeg_check_combined_header: env::var("FFMPEG_CHECK_COMBINED_HEADER")
                 .map(|v| v.trim().parse().unwrap_or(false)).unwrap_or(false),
+            ffmpeg_rust_target: env::var("FFMPEG_RUST_TARGET").ok()
+                .map(|v| parse_rust_target(&v)
+                    .unwrap_or_else(|e| panic!("Invalid FFMPEG_RUST_TARGET `{v}`: {e}")))
+                .unwrap_or_else(default_rust_target),
+            ffmpeg_extra_libs: extra_args_from_env("FFMPEG_EXTRA_LIBS"),
+            rockchip_rga_meson_opts: extra_args_from_env("ROCKCHIP_RGA_MESON_OPTS"),
+            meson_c_args: extra_args_from_env("MESON_C_ARGS"),
+            meson_c_link_args: extra_args_from_env("MESON_C_LINK_ARGS"),
+            meson_pkg_config_libdir: env::var("MESON_PKG_CONFIG_LIBDIR").ok()
+                .filter(|v| !v.trim().is_empty()),
+            rockchip_mpp_cmake_opts: extra_args_from_env("ROCKCHIP_MPP_CMAKE_OPTS"),
+            rockchip_mpp_prebuilt: rockchip_mpp_prebuilt_from_env(),
+        }
+    }
+}
+
+fn default_rust_target() -> RustTarget {
+    parse_rust_target(helpers::DEFAULT_RUST_TARGET).unwrap()
+}
+
+/// Parses a stable Rust version like `1.68` or `1.68.2` into a bindgen
+/// `RustTarget`.
+fn parse_rust_target(value: &str) -> Result<RustTarget, String> {
+    let (minor, patch) = helpers::parse_rust_version(value)?;
+    RustTarget::stable(minor, patch).map_err(|e| e.to_string())
+}
+
+/// Returns the `pkg-config` binary set for the target, looking up the same
+/// env vars as the `pkg_config` crate: `PKG_CONFIG_<target>`,
+/// `PKG_CONFIG_<target_with_underscores>`, `TARGET_PKG_CONFIG` (or
+/// `HOST_PKG_CONFIG` for native builds) and `PKG_CONFIG`.
+fn pkg_config_from_env(target: &str, host: &str) -> Option<String> {
+    let kind = if target == host { "HOST" } else { "TARGET" };
+    [
+        format!("PKG_CONFIG_{target}"),
+        format!("PKG_CONFIG_{}", target.replace('-', "_")),
+        format!("{kind}_PKG_CONFIG"),
+        "PKG_CONFIG".to_string(),
+    ]
+        .into_iter()
+        .find_map(|var_name| {
+            println!("cargo:rerun-if-env-changed={var_name}");
+            env::var(var_name).ok().filter(|v| !v.trim().is_empty())
+        })
+}
+
+/// Returns the install prefix of an FFmpeg build shared between several crates,
+/// checking that it contains the headers and the pkg-config files.
+fn ffmpeg_install_dir_from_env() -> Option<PathBuf> {
+    let install_dir = PathBuf::from(env::var("FFMPEG_INSTALL_DIR").ok()?);
+    for subdir in ["include", "lib/pkgconfig"] {
+        let dir = install_dir.join(subdir);
+        if !dir.is_dir() {
+            panic!("FFMPEG_INSTALL_DIR is not an FFmpeg install prefix: {dir:?} doesn't exist");
+        }
+    }
+    Some(install_dir)
+}
+
+/// A `librockchip_mpp` installed outside of the build, e.g. by the vendor SDK
+/// of a board, used instead of building `vendor/rockchip-mpp`
+struct PrebuiltMpp {
+    lib_dir: PathBuf,
+    /// The dir containing the `rockchip` headers dir
+    include_dir: PathBuf,
+}
+
+fn rockchip_mpp_prebuilt_from_env() -> Option<PrebuiltMpp> {
+    let (lib_dir, include_dir) = match (
+        env::var("ROCKCHIP_MPP_LIB_DIR").ok(),
+        env::var("ROCKCHIP_MPP_INCLUDE_DIR").ok(),
+    ) {
+        (Some(lib_dir), Some(include_dir)) => (PathBuf::from(lib_dir), PathBuf::from(include_dir)),
+        (None, None) => return None,
+        _ => panic!("ROCKCHIP_MPP_LIB_DIR and ROCKCHIP_MPP_INCLUDE_DIR must be set together"),
+    };
+    // The linker needs the unversioned name, which only comes with the dev package
+    let has_lib = ["librockchip_mpp.so", "librockchip_mpp.a"]
+        .iter()
+        .any(|lib_name| lib_dir.join(lib_name).exists());
+    if !has_lib {
+        panic!(
+            "ROCKCHIP_MPP_LIB_DIR doesn't contain librockchip_mpp.so or librockchip_mpp.a: {lib_dir}"
+        );
+    }
+    let header_path = include_dir.join("rockchip").join("rk_mpi.h");
+    if !header_path.exists() {
+        panic!("ROCKCHIP_MPP_INCLUDE_DIR doesn't contain the MPP headers: {header_path} doesn't exist");
+    }
+    Some(PrebuiltMpp { lib_dir, include_dir })
+}
+
+/// Writes a `rockchip_mpp.pc` file for a prebuilt MPP, so FFmpeg's configure
+/// and the final link find it the same way as the vendored one. Returns the
+/// dir of the file.
+fn write_prebuilt_mpp_pkg_config(prebuilt_mpp: &PrebuiltMpp, out_dir: &Path) -> PathBuf {
+    let pkg_config_dir = out_dir.join("rockchip-mpp-prebuilt").join("pkgconfig");
+    fs::create_dir_all(&pkg_config_dir)
+        .unwrap_or_else(|e| panic!("Cannot create {pkg_config_dir}: {e}"));
+    let PrebuiltMpp { lib_dir, include_dir } = prebuilt_mpp;
+    // The version of a prebuilt library is unknown, claim the one FFmpeg requires
+    let pc_content = indoc::formatdoc! {"
+        libdir={lib_dir}
+        includedir={include_dir}
+
+        Name: rockchip_mpp
+        Description: Rockchip Media Process Platform (prebuilt)
+        Version: 1.3.8
+        Libs: -L${{libdir}} -lrockchip_mpp
+        Cflags: -I${{includedir}}
+    "};
+    let pc_path = pkg_config_dir.join("rockchip_mpp.pc");
+    fs::write(&pc_path, pc_content).unwrap_or_else(|e| panic!("Cannot write {pc_path}: {e}"));
+    pkg_config_dir
+}
+
+/// File name of a shared library, `lib_name` includes the `lib` prefix.
+fn shared_lib_file_name(lib_name: &str, target_os: &str) -> String {
+    match target_os {
+        "macos" | "ios" => format!("{lib_name}.dylib"),
+        _ => format!("{lib_name}.so"),
+    }
+}
+
+/// Checks that a pre-built install prefix provides the shared libraries and
+/// adds its lib dir to the rpath, so the binaries find them at runtime.
+///
+/// On macOS the libraries must be built with `--install-name-dir=@rpath`,
+/// otherwise their install names point to the original build location.
+fn linking_shared_install_dir(ffmpeg_install_dir: &Path) {
+    let target_os = env::var("CARGO_CFG_TARGET_OS").expect("CARGO_CFG_TARGET_OS env var");
+    let lib_dir = ffmpeg_install_dir.join("lib");
+    for lib_name in LIBS {
+        let lib_path = lib_dir.join(shared_lib_file_name(lib_name, &target_os));
+        if !lib_path.exists() {
+            panic!("{lib_path} not found, FFMPEG_INSTALL_DIR must contain shared libraries in dynamic link mode");
         }
     }
+    println!("cargo:rustc-link-arg=-Wl,-rpath,{lib_dir}");
+}
+
+/// Collects FFmpeg configure flags from `FFMPEG_CONFIGURATION_FILE` followed by
+/// the ones from `FFMPEG_CONFIGURATION`, so the inline flags take precedence.
+fn ffmpeg_configuration_from_env() -> Vec<String> {
+    let configuration_file = env::var("FFMPEG_CONFIGURATION_FILE").ok();
+    let configuration = env::var("FFMPEG_CONFIGURATION").ok();
+    // The flags of the features come first, so the env vars can override them
+    let mut flags = helpers::default_configuration(
+        cfg!(feature = "default-encoders"),
+        cfg!(feature = "default-rkmpp"),
+    );
+    if configuration_file.is_none() && configuration.is_none() && flags.is_empty() {
+        panic!(
+            "FFMPEG_CONFIGURATION or FFMPEG_CONFIGURATION_FILE env var, \
+            or one of the default-encoders and default-rkmpp features"
+        );
+    }
+
+    let file_content = configuration_file.map(|configuration_file| {
+        println!("cargo:rerun-if-changed={configuration_file}");
+        fs::read_to_string(&configuration_file)
+            .unwrap_or_else(|e| panic!("Cannot read {configuration_file}: {e}"))
+    });
+    flags.extend(helpers::configuration_flags(file_content.as_deref(), configuration.as_deref()));
+    flags
+}
+
+/// Splits a space-separated list of extra arguments for a build tool.
+///
+/// The arguments are passed to the tool directly rather than through a shell,
+/// so shell syntax would only end up as garbage in the tool's arguments.
+fn extra_args_from_env(var_name: &str) -> Vec<String> {
+    const SHELL_METACHARACTERS: &[char] = &[
+        ';', '|', '&', '$', '`', '<', '>', '(', ')', '{', '}', '\\', '"', '\'', '*', '?', '\n',
+    ];
+    let Ok(value) = env::var(var_name) else {
+        return vec!();
+    };
+    if let Some(c) = value.chars().find(|c| SHELL_METACHARACTERS.contains(c)) {
+        panic!(
+            "{var_name} contains shell metacharacter {c:?}, \
+            arguments are passed to the build tool as is without a shell"
+        );
+    }
+    value.split(' ')
+        .filter(|v| !v.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+/// Checks if the cross toolchain ships `pthread.h`
+fn cross_toolchain_has_pthreads(cross_toolchain_prefix: &str) -> bool {
+    let mut cc = Command::new(format!("{cross_toolchain_prefix}gcc"))
+        .args(["-E", "-x", "c", "-o", "/dev/null", "-"])
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .expect("Failed to run cross compiler");
+    cc.stdin.take()
+        .expect("Missing cross compiler stdin")
+        .write_all(b"#include <pthread.h>\n")
+        .expect("Failed to write to cross compiler stdin");
+    cc.wait()
+        .expect("Failed to wait for cross compiler")
+        .success()
+}
+
+/// Identifies a compiler by its path, its modification time and its
+/// `--version` output, so that a compiler updated in place or another one
+/// found in `PATH` is noticed.
+fn compiler_fingerprint(cc: &str) -> String {
+    let cc_path = if cc.contains('/') {
+        Some(std::path::PathBuf::from(cc))
+    } else {
+        env::var_os("PATH").and_then(|paths| {
+            env::split_paths(&paths)
+                .map(|dir| dir.join(cc))
+                .find(|path| path.is_file())
+        })
+    };
+    let modified = cc_path.as_ref()
+        .and_then(|path| fs::metadata(path).ok()?.modified().ok());
+    let version = Command::new(cc)
+        .arg("--version")
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).into_owned())
+        .unwrap_or_default();
+    format!("{cc_path:?} {modified:?}\n{version}")
 }
 
 /// clang doesn't support -I{verbatim path} on windows, so we need to remove it if possible.
@@ -323,7 +641,7 @@ fn remove_verbatim(path: String) -> PathBuf {
 mod pkg_config_linking {
     use super::*;
 
-    /// Returns error when some library are missing. Otherwise, returns the paths of the libraries.
+    /// Returns error when some library are missing. Otherwise, returns the dirs of the libraries.
     ///
     /// Note: no side effect if this function errors.
     pub fn linking_with_pkg_config(
@@ -342,19 +660,106 @@ mod pkg_config_linking {
         }
 
         // real linking
-        let mut paths = HashSet::new();
+        let mut paths = Vec::new();
         for libname in library_names {
             let new_paths = pkg_config::Config::new()
                 .statik(statik)
                 .probe(libname)
                 .unwrap_or_else(|_| panic!("{} not found!", libname))
-                .include_paths;
+                .link_paths;
             for new_path in new_paths {
-                let new_path = new_path.to_str().unwrap().to_string();
-                paths.insert(new_path);
+                let new_path = PathBuf::from(new_path.to_str().unwrap());
+                if !paths.contains(&new_path) {
+                    paths.push(new_path);
+                }
             }
         }
-        Ok(paths.into_iter().map(PathBuf::from).collect())
+        Ok(paths)
+    }
+
+    /// Emits the system libraries from `Libs.private` of all the `.pc` files
+    /// in the given pkg-config directories.
+    ///
+    /// The pkg-config crate handles `-l` entries in static mode but skips
+    /// other flags such as `-pthread`, which FFmpeg puts there.
+    pub fn linking_private_libs(pkg_config_dirs: &[PathBuf]) {
+        let mut private_libs = Vec::new();
+        for pkg_config_dir in pkg_config_dirs {
+            let Ok(entries) = fs::read_dir(pkg_config_dir) else {
+                continue;
+            };
+            for entry in entries {
+                let pc_path = entry.expect("Cannot read pkg-config dir entry").path();
+                if pc_path.extension().is_none_or(|ext| ext != "pc") {
+                    continue;
+                }
+                let pc_content = fs::read_to_string(&pc_path)
+                    .unwrap_or_else(|e| panic!("Cannot read {pc_path:?}: {e}"));
+                for lib in helpers::parse_private_libs(&pc_content) {
+                    // Libraries built along with FFmpeg are linked by pkg-config
+                    let is_vendored = pkg_config_dirs.iter().any(|dir| {
+                        dir.parent()
+                            .is_some_and(|lib_dir| lib_dir.join(format!("lib{lib}.a")).exists())
+                    });
+                    if !is_vendored && !private_libs.contains(&lib) {
+                        private_libs.push(lib);
+                    }
+                }
+            }
+        }
+        for lib in private_libs {
+            println!("cargo:rustc-link-lib={lib}");
+        }
+    }
+}
+
+/// Path of the bindings checked into the crate for the target, used instead of
+/// running bindgen with the `prebuilt_bindings` feature.
+fn prebuilt_binding_path(target: &str) -> Option<PathBuf> {
+    if !cfg!(feature = "prebuilt_bindings") {
+        return None;
+    }
+    let manifest_dir = env::var("CARGO_MANIFEST_DIR").expect("CARGO_MANIFEST_DIR env var");
+    let binding_path = Path::new(&manifest_dir)
+        .join("src")
+        .join("prebuilt")
+        .join(format!("binding_{target}.rs"));
+    println!("cargo:rerun-if-changed={binding_path}");
+    if !binding_path.exists() {
+        println!("cargo:warning=No prebuilt bindings for {target}, generating them with bindgen");
+        return None;
+    }
+    Some(binding_path)
+}
+
+fn use_prebuilt_binding(from: &Path, to: &Path) {
+    fs::copy(from, to).expect("Prebuilt binding file failed to be copied.");
+}
+
+/// How the FFmpeg libraries were found, reported in a single line to debug
+/// builds linking an unexpected FFmpeg
+struct LinkingSummary {
+    /// E.g. `pkg-config (vendored build)`
+    method: &'static str,
+    /// Either `prebuilt` or `bindgen`
+    bindings: &'static str,
+    include_dir: Option<PathBuf>,
+    lib_dirs: Vec<PathBuf>,
+    link_mode: FFmpegLinkMode,
+}
+
+impl std::fmt::Display for LinkingSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let lib_dirs = self.lib_dirs.iter().map(|dir| dir.as_str()).collect::<Vec<_>>();
+        write!(
+            f,
+            "FFmpeg linking: {}, {} bindings, include dir {}, lib dirs {}, link mode {}",
+            self.method,
+            self.bindings,
+            self.include_dir.as_ref().map_or("none", |dir| dir.as_str()),
+            if lib_dirs.is_empty() { "none".to_string() } else { lib_dirs.join(":") },
+            self.link_mode,
+        )
     }
 }
 
@@ -362,25 +767,38 @@ fn linking(
     env_vars: &EnvVars,
     ffmpeg_include_dir: &Path,
     pkg_config_path: &str,
-) {
+) -> LinkingSummary {
     let output_binding_path = &env_vars.out_dir.join("binding.rs");
 
     #[cfg(not(target_os = "windows"))]
     {
+        /// Returns the lib dirs and where the bindings come from
         fn linking_with_pkg_config_and_bindgen(
             env_vars: &EnvVars,
             ffmpeg_include_dir: &Path,
             output_binding_path: &Path,
-        ) -> Result<(), pkg_config::Error> {
+        ) -> Result<(Vec<PathBuf>, &'static str), pkg_config::Error> {
             // Probe libraries(enable emitting cargo metadata)
-            pkg_config_linking::linking_with_pkg_config(
+            let lib_dirs = pkg_config_linking::linking_with_pkg_config(
                 LIBS,
                 env_vars.ffmpeg_link_mode.is_static(),
             )?;
-            generate_bindings(ffmpeg_include_dir, &HEADERS)
+            if let Some(prebuilt_binding_path) = prebuilt_binding_path(&env_vars.target) {
+                use_prebuilt_binding(&prebuilt_binding_path, output_binding_path);
+                Ok((lib_dirs, "prebuilt"))
+            } else {
+                generate_bindings(
+                    ffmpeg_include_dir,
+                    &HEADERS,
+                    &env_vars.out_dir,
+                    env_vars.ffmpeg_rust_target,
+                    env_vars.ffmpeg_verbose,
+                    env_vars.ffmpeg_check_combined_header,
+                )
                 .write_to_file(output_binding_path)
                 .expect("Cannot write binding to file.");
-            Ok(())
+                Ok((lib_dirs, "bindgen"))
+            }
         }
         // Detect if we are inside a nix shell
         if env::var("PKG_CONFIG_PATH_FOR_TARGET").is_ok() {
@@ -388,8 +806,28 @@ fn linking(
         } else {
             env::set_var("PKG_CONFIG_PATH", pkg_config_path);
         }
-        linking_with_pkg_config_and_bindgen(&env_vars, ffmpeg_include_dir, output_binding_path)
-            .expect("Static linking with pkg-config failed.");
+        let (lib_dirs, bindings) =
+            linking_with_pkg_config_and_bindgen(&env_vars, ffmpeg_include_dir, output_binding_path)
+                .expect("Static linking with pkg-config failed.");
+        if env_vars.ffmpeg_link_mode.is_static() {
+            let pkg_config_dirs = pkg_config_path
+                .split(':')
+                .filter(|dir| !dir.is_empty())
+                .map(PathBuf::from)
+                .collect::<Vec<_>>();
+            pkg_config_linking::linking_private_libs(&pkg_config_dirs);
+        }
+        LinkingSummary {
+            method: if env_vars.ffmpeg_install_dir.is_some() {
+                "pkg-config (FFMPEG_INSTALL_DIR)"
+            } else {
+                "pkg-config (vendored build)"
+            },
+            bindings,
+            include_dir: Some(ffmpeg_include_dir.to_owned()),
+            lib_dirs,
+            link_mode: env_vars.ffmpeg_link_mode,
+        }
     }
 
     #[cfg(target_os = "windows")]
@@ -398,21 +836,48 @@ fn linking(
             linking_with_libs_dir(
                 LIBS,
                 ffmpeg_libs_dir,
-                env_vars.ffmpeg_link_mode.unwrap_or(FFmpegLinkMode::Static),
+                env_vars.ffmpeg_link_mode,
             );
-            if let Some(ffmpeg_binding_path) = env_vars.ffmpeg_binding_path.as_ref() {
+            let bindings = if let Some(ffmpeg_binding_path) = env_vars.ffmpeg_binding_path.as_ref()
+            {
                 use_prebuilt_binding(ffmpeg_binding_path, output_binding_path);
+                "prebuilt"
             } else if let Some(ffmpeg_include_dir) = env_vars.ffmpeg_include_dir.as_ref() {
-                generate_bindings(ffmpeg_include_dir, &HEADERS)
-                    .write_to_file(output_binding_path)
-                    .expect("Cannot write binding to file.");
+                generate_bindings(
+                    ffmpeg_include_dir,
+                    &HEADERS,
+                    &env_vars.out_dir,
+                    env_vars.ffmpeg_rust_target,
+                    env_vars.ffmpeg_verbose,
+                    env_vars.ffmpeg_check_combined_header,
+                )
+                .write_to_file(output_binding_path)
+                .expect("Cannot write binding to file.");
+                "bindgen"
             } else {
                 panic!("No binding generation method is set!");
+            };
+            LinkingSummary {
+                method: "FFMPEG_LIBS_DIR",
+                bindings,
+                include_dir: env_vars.ffmpeg_include_dir.clone(),
+                lib_dirs: vec![ffmpeg_libs_dir.clone()],
+                link_mode: env_vars.ffmpeg_link_mode,
             }
         } else {
             #[cfg(feature = "link_vcpkg_ffmpeg")]
-            vcpkg_linking::linking_with_vcpkg_and_bindgen(&env_vars, output_binding_path)
-                .expect("Linking FFmpeg with vcpkg failed.");
+            {
+                vcpkg_linking::linking_with_vcpkg_and_bindgen(&env_vars, output_binding_path)
+                    .expect("Linking FFmpeg with vcpkg failed.");
+                // vcpkg emits its own search paths
+                LinkingSummary {
+                    method: "vcpkg",
+                    bindings: "bindgen",
+                    include_dir: None,
+                    lib_dirs: vec![],
+                    link_mode: env_vars.ffmpeg_link_mode,
+                }
+            }
             #[cfg(not(feature = "link_vcpkg_ffmpeg"))]
             panic!(
                 "
@@ -425,8 +890,220 @@ Enable `link_vcpkg_ffmpeg` feature if you want to link ffmpeg provided by vcpkg.
     }
 }
 
-fn build_ffmpeg(env_vars: &EnvVars) -> (PathBuf, String) {
+/// A failure of the vendored libraries build
+#[derive(Debug)]
+struct BuildError {
+    /// What was being done, e.g. "building libdrm"
+    phase: String,
+    kind: BuildErrorKind,
+}
+
+#[derive(Debug)]
+enum BuildErrorKind {
+    /// The command could not be started
+    Spawn { command: String, error: io::Error },
+    /// The command exited unsuccessfully
+    Status { command: String, status: ExitStatus },
+    /// A file of the build could not be written or removed
+    Io { path: PathBuf, error: io::Error },
+    /// The build can't start, e.g. a build tool is missing
+    Invalid(String),
+    /// Pairs of contradicting configure flags with `FFMPEG_CONFIGURATION_STRICT`
+    ConflictingFlags(Vec<(String, String)>),
+}
+
+impl BuildError {
+    fn new(phase: &str, kind: BuildErrorKind) -> Self {
+        Self { phase: phase.to_string(), kind }
+    }
+
+    fn io(phase: &str, path: &Path, error: io::Error) -> Self {
+        Self::new(phase, BuildErrorKind::Io { path: path.to_owned(), error })
+    }
+
+    fn invalid(phase: &str, message: String) -> Self {
+        Self::new(phase, BuildErrorKind::Invalid(message))
+    }
+
+    /// Whether a command failed, its output then explains the error
+    fn is_command_failure(&self) -> bool {
+        matches!(self.kind, BuildErrorKind::Spawn { .. } | BuildErrorKind::Status { .. })
+    }
+}
+
+impl std::fmt::Display for BuildError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let phase = &self.phase;
+        match &self.kind {
+            BuildErrorKind::Spawn { command, error } => write!(f, "Failed {phase}, cannot run `{command}`: {error}"),
+            BuildErrorKind::Status { command, status } => write!(f, "Failed {phase}, `{command}` {status}"),
+            BuildErrorKind::Io { path, error } => write!(f, "Failed {phase}, {path}: {error}"),
+            BuildErrorKind::Invalid(message) => write!(f, "Failed {phase}: {message}"),
+            BuildErrorKind::ConflictingFlags(conflicts) => {
+                let conflicts = conflicts.iter()
+                    .map(|(flag, other_flag)| format!("{flag} conflicts with {other_flag}"))
+                    .collect::<Vec<_>>();
+                write!(f, "Failed {phase}, conflicting flags: {}", conflicts.join(", "))
+            }
+        }
+    }
+}
+
+/// Runs a build command, inheriting its output. `id` names the phase in the
+/// progress markers, `phase` describes it in the errors.
+fn run_build_cmd(id: &str, phase: &str, cmd: &mut Command) -> Result<(), BuildError> {
+    let command = format!("{cmd:?}");
+    report_progress(id, "start");
+    let started_at = Instant::now();
+    let result = match cmd.status() {
+        Ok(status) if status.success() => Ok(()),
+        Ok(status) => Err(BuildError::new(phase, BuildErrorKind::Status { command, status })),
+        Err(error) => Err(BuildError::new(phase, BuildErrorKind::Spawn { command, error })),
+    };
+    let outcome = if result.is_ok() { "done" } else { "failed" };
+    report_progress(id, &format!("{outcome}:elapsed={:.1}s", started_at.elapsed().as_secs_f64()));
+    result
+}
+
+/// Whether to print the build phase markers, see [`report_progress`]
+static BUILD_PROGRESS: AtomicBool = AtomicBool::new(false);
+
+/// Prints a machine-readable marker of a build phase with
+/// `FFMPEG_BUILD_PROGRESS`, e.g. `rusty_ffmpeg:phase=ffmpeg/build:start`.
+fn report_progress(id: &str, event: &str) {
+    if BUILD_PROGRESS.load(Ordering::Relaxed) {
+        println!("cargo:warning=rusty_ffmpeg:phase={id}:{event}");
+    }
+}
+
+/// Checks that the build tools are installed, so a missing tool is reported
+/// by name rather than with a bare "No such file or directory" error.
+fn check_build_tools(env_vars: &EnvVars) -> Result<(), BuildError> {
+    let mut tools = vec!(("git", "to build FFmpeg"), ("make", "to build FFmpeg"));
+    if env_vars.ffmpeg_rockchip_mpp {
+        tools.extend([
+            ("meson", "to build with FFMPEG_ROCKCHIP_MPP"),
+            ("ninja", "to build with FFMPEG_ROCKCHIP_MPP"),
+        ]);
+        if env_vars.rockchip_mpp_prebuilt.is_none() {
+            tools.push(("cmake", "to build with FFMPEG_ROCKCHIP_MPP"));
+        }
+    }
+    for (tool, purpose) in tools {
+        let found = Command::new(tool)
+            .arg("--version")
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .is_ok_and(|status| status.success());
+        if !found {
+            return Err(BuildError::invalid(
+                "checking the build tools",
+                format!("{tool} not found; install it {purpose}"),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the vendored sources are checked out, as a missing submodule
+/// otherwise fails deep in the build with a confusing error.
+fn check_vendored_sources(env_vars: &EnvVars) -> Result<(), BuildError> {
+    let mut sources = vec!(("vendor/ffmpeg", "configure"));
+    if env_vars.ffmpeg_rockchip_mpp {
+        sources.extend([
+            ("vendor/libdrm", "meson.build"),
+            ("vendor/rockchip-librga", "meson.build"),
+        ]);
+        if env_vars.rockchip_mpp_prebuilt.is_none() {
+            sources.push(("vendor/rockchip-mpp", "CMakeLists.txt"));
+        }
+    }
+    helpers::check_vendored_sources(std::path::Path::new("."), &sources)
+        .map_err(|message| BuildError::invalid("checking the vendored sources", message))
+}
+
+/// Sets up a meson or cmake build dir unless it was already set up with the
+/// same command, so the following builds only run `ninja`.
+///
+/// A build dir set up with another command, or without `build.ninja` (e.g. an
+/// interrupted setup), is removed and set up from scratch. Returns whether the
+/// setup command was run.
+fn setup_build_dir(setup_cmd: &mut Command, build_dir: &Path, name: &str) -> Result<bool, BuildError> {
+    let setup_stamp_path = build_dir.join(".rusty_ffmpeg_setup");
+    let setup_fingerprint = format!("{setup_cmd:?}");
+    if build_dir.join("build.ninja").exists()
+        && fs::read_to_string(&setup_stamp_path).is_ok_and(|stamp| stamp == setup_fingerprint)
+    {
+        eprintln!("{name} build dir is up to date, skipping setup");
+        report_progress(&format!("{name}/setup"), "skipped");
+        return Ok(false);
+    }
+    let phase = format!("setting up {name}");
+    if build_dir.exists() {
+        fs::remove_dir_all(build_dir).map_err(|e| BuildError::io(&phase, build_dir, e))?;
+    }
+    run_build_cmd(&format!("{name}/setup"), &phase, setup_cmd)?;
+    fs::write(&setup_stamp_path, setup_fingerprint)
+        .map_err(|e| BuildError::io(&phase, &setup_stamp_path, e))?;
+    Ok(true)
+}
+
+/// Builds and installs `vendor/rockchip-mpp`, returning the install prefix.
+fn build_rockchip_mpp(
+    env_vars: &EnvVars,
+    cmake_toolchain_path: Option<&str>,
+    ninja_verbose_args: &[&str],
+) -> Result<PathBuf, BuildError> {
+    let rockchip_mpp_out_dir = env_vars.out_dir.join("rockchip-mpp");
+    let rockchip_mpp_build_dir = rockchip_mpp_out_dir.join("cmake");
+    let rockchip_mpp_install_dir = rockchip_mpp_out_dir.join("install");
+    let mut rockchip_mpp_configure_cmd = Command::new("cmake");
+    rockchip_mpp_configure_cmd
+        .arg("-GNinja")
+        .arg("-DBUILD_TEST=false")
+        .arg(format!(
+            "-DCMAKE_POSITION_INDEPENDENT_CODE={}",
+            if env_vars.ffmpeg_pic { "ON" } else { "OFF" }
+        ))
+        .arg(format!("-DCMAKE_INSTALL_PREFIX={rockchip_mpp_install_dir}"))
+        .arg(format!("-Svendor/rockchip-mpp"))
+        .arg(format!("-B{rockchip_mpp_build_dir}"));
+    if let Some(cmake_toolchain_path) = cmake_toolchain_path {
+        rockchip_mpp_configure_cmd
+            .args(["--toolchain", cmake_toolchain_path]);
+    }
+    if let Some(opt) = env_vars.rockchip_mpp_cmake_opts.iter().find(|opt| !opt.starts_with("-D")) {
+        return Err(BuildError::invalid(
+            "configuring rockchip-mpp",
+            format!("ROCKCHIP_MPP_CMAKE_OPTS only accepts -D<var>=<value> entries, got {opt}"),
+        ));
+    }
+    rockchip_mpp_configure_cmd.args(&env_vars.rockchip_mpp_cmake_opts);
+    eprintln!("Configuring rockchip-mpp: {rockchip_mpp_configure_cmd:?}");
+    setup_build_dir(&mut rockchip_mpp_configure_cmd, &rockchip_mpp_build_dir, "rockchip-mpp")?;
+    run_build_cmd(
+        "rockchip-mpp/build",
+        "building rockchip-mpp",
+        Command::new("ninja")
+            .args([
+                "-C", rockchip_mpp_build_dir.as_str(),
+                "install",
+            ])
+            .args(ninja_verbose_args),
+    )?;
+    Ok(rockchip_mpp_install_dir)
+}
+
+fn build_ffmpeg(env_vars: &EnvVars) -> Result<(PathBuf, String), BuildError> {
+    BUILD_PROGRESS.store(env_vars.ffmpeg_build_progress, Ordering::Relaxed);
+    check_build_tools(env_vars)?;
+    check_vendored_sources(env_vars)?;
+    let ninja_verbose_args: &[&str] = if env_vars.ffmpeg_verbose { &["-v"] } else { &[] };
+    let make_verbose_args: &[&str] = if env_vars.ffmpeg_verbose { &["V=1"] } else { &[] };
+
     let target_os = env::var("CARGO_CFG_TARGET_OS").expect("CARGO_CFG_TARGET_OS env var");
+    let target_env = env::var("CARGO_CFG_TARGET_ENV").unwrap_or_default();
     let target_arch = env::var("CARGO_CFG_TARGET_ARCH").expect("CARGO_CFG_TARGET_ARCH env var");
     let cpu_arch = match target_arch.as_str() {
         "aarch64" => "armv8-a",
@@ -437,41 +1114,44 @@ fn build_ffmpeg(env_vars: &EnvVars) -> (PathBuf, String) {
     let (meson_cross_path, ffmpeg_cross_opts) =
         if let Ok(cross_toolchain_prefix) = env::var("CROSS_TOOLCHAIN_PREFIX")
     {
+        // Hard-float ARM targets (e.g. armv7-unknown-linux-gnueabihf) need the
+        // float ABI set explicitly, otherwise soft and hard float objects get mixed
+        let is_hard_float = env::var("CARGO_CFG_TARGET_ABI")
+            .map_or_else(|_| env_vars.target.ends_with("hf"), |abi| abi.ends_with("hf"));
+        let float_abi_cflags: &[&str] = if target_arch == "arm" && is_hard_float {
+            &["-mfpu=neon", "-mfloat-abi=hard"]
+        } else {
+            &[]
+        };
         let meson_cross_path = env_vars.out_dir.join("meson_cross.txt");
-        let mut meson_cross_file = File::create(&meson_cross_path)
-            .expect("Failed to create meson_cross.txt file");
-        meson_cross_file.write_all(
-            indoc::formatdoc! {"
-                [binaries]
-                c = '{cross_toolchain_prefix}gcc'
-                cpp = '{cross_toolchain_prefix}g++'
-                ar = '{cross_toolchain_prefix}ar'
-                strip = '{cross_toolchain_prefix}strip'
-
-                [host_machine]
-                system = 'linux'
-                cpu_family = 'x86_64'
-                cpu = 'x86_64'
-                endian = 'little'
-
-                [properties]
-                needs_exe_wrapper = true
-            "}.as_bytes()
-        ).expect("Failed to write meson_cross.txt file");
-        (
-            Some(meson_cross_path),
-            Some([
-                "--enable-cross-compile".to_string(),
-                format!("--cc={cross_toolchain_prefix}gcc"),
-                format!("--cxx={cross_toolchain_prefix}g++"),
-                format!("--ld={cross_toolchain_prefix}g++"),
-                format!("--ar={cross_toolchain_prefix}ar"),
-                format!("--strip={cross_toolchain_prefix}strip"),
-                format!("--cpu={cpu_arch}"),
-                format!("--target-os={target_os}"),
-                format!("--arch={target_arch}"),
-            ])
-        )
+        let meson_cross_content = helpers::meson_cross_file_content(
+            &cross_toolchain_prefix,
+            &target_arch,
+            float_abi_cflags,
+            &env_vars.meson_c_args,
+            &env_vars.meson_c_link_args,
+            env_vars.meson_pkg_config_libdir.as_deref(),
+        );
+        fs::write(&meson_cross_path, meson_cross_content)
+            .map_err(|e| BuildError::io("writing the meson cross file", &meson_cross_path, e))?;
+        let mut ffmpeg_cross_opts = vec!(
+            "--enable-cross-compile".to_string(),
+            format!("--cc={cross_toolchain_prefix}gcc"),
+            format!("--cxx={cross_toolchain_prefix}g++"),
+            format!("--ld={cross_toolchain_prefix}g++"),
+            format!("--ar={cross_toolchain_prefix}ar"),
+            format!("--strip={cross_toolchain_prefix}strip"),
+            format!("--cpu={}", env_vars.ffmpeg_cpu.as_deref().unwrap_or(cpu_arch)),
+            format!(
+                "--target-os={}",
+                env_vars.ffmpeg_target_os.clone().unwrap_or_else(|| helpers::ffmpeg_target_os(&target_os, &target_env))
+            ),
+            format!("--arch={target_arch}"),
+        );
+        if !float_abi_cflags.is_empty() {
+            ffmpeg_cross_opts.push(format!("--extra-cflags={}", float_abi_cflags.join(" ")));
+        }
+        (Some(meson_cross_path), Some(ffmpeg_cross_opts))
     } else {
         (None, None)
     };
@@ -506,21 +1186,22 @@ fn build_ffmpeg(env_vars: &EnvVars) -> (PathBuf, String) {
                 "-Damdgpu=disabled",
                 "-Dnouveau=disabled",
                 "-Dvmwgfx=disabled",
-            ]);
-        let libdrm_setup_status = libdrm_setup_cmd
-            .status()
-            .expect("Failed to run libdrm setup");
-        assert!(libdrm_setup_status.success(), "Error setting up libdrm");
-        let libdrm_configure_status = Command::new("meson")
-            .args(["configure", libdrm_build_dir.as_str()])
-            .status()
-            .expect("Failed to run libdrm configuration");
-        assert!(libdrm_configure_status.success(), "Error configuring libdrm");
-        let libdrm_build_status = Command::new("ninja")
-            .args(["-C", libdrm_build_dir.as_str(), "install"])
-            .status()
-            .expect("Failed to run libdrm building");
-        assert!(libdrm_build_status.success(), "Error building libdrm");
+            ])
+            .arg(format!("-Db_staticpic={}", env_vars.ffmpeg_pic));
+        if setup_build_dir(&mut libdrm_setup_cmd, &libdrm_build_dir, "libdrm")? {
+            run_build_cmd(
+                "libdrm/configure",
+                "configuring libdrm",
+                Command::new("meson").args(["configure", libdrm_build_dir.as_str()]),
+            )?;
+        }
+        run_build_cmd(
+            "libdrm/build",
+            "building libdrm",
+            Command::new("ninja")
+                .args(["-C", libdrm_build_dir.as_str(), "install"])
+                .args(ninja_verbose_args),
+        )?;
 
         let rockchip_librga_out_dir = env_vars.out_dir.join("rockchip-librga");
         let rockchip_librga_build_dir = rockchip_librga_out_dir.join("meson");
@@ -546,58 +1227,52 @@ fn build_ffmpeg(env_vars: &EnvVars) -> (PathBuf, String) {
                 "-Dlibdrm=false",
                 "-Dlibrga_demo=false",
                 "-Dbuild_test=false",
-            ]);
-        let rockchip_librga_setup_status = rockchip_librga_setup_cmd
-            .status()
-            .expect("Failed to run rockchip-librga setup");
-        assert!(rockchip_librga_setup_status.success(), "Error setting up rockchip-librga");
-        let rockchip_librga_configure_status = Command::new("meson")
-            .args(["configure", rockchip_librga_build_dir.as_str()])
-            .status()
-            .expect("Failed to run rockchip-librga configuration");
-        assert!(rockchip_librga_configure_status.success(), "Error configuring rockchip-librga");
-        let rockchip_librga_build_status = Command::new("ninja")
-            .args(["-C", rockchip_librga_build_dir.as_str(), "install"])
-            .status()
-            .expect("Failed to run rockchip-librga building");
-        assert!(rockchip_librga_build_status.success(), "Error building rockchip-librga");
-
-        let rockchip_mpp_out_dir = env_vars.out_dir.join("rockchip-mpp");
-        let rockchip_mpp_build_dir = rockchip_mpp_out_dir.join("cmake");
-        let rockchip_mpp_install_dir = rockchip_mpp_out_dir.join("install");
-        let rockchip_mpp_pkg_config_path = rockchip_mpp_install_dir.join("lib").join("pkgconfig");
-        let mut rockchip_mpp_configure_cmd = Command::new("cmake");
-        rockchip_mpp_configure_cmd
-            .arg("-GNinja")
-            .arg("-DBUILD_TEST=false")
-            .arg(format!("-DCMAKE_INSTALL_PREFIX={rockchip_mpp_install_dir}"))
-            .arg(format!("-Svendor/rockchip-mpp"))
-            .arg(format!("-B{rockchip_mpp_build_dir}"));
-        if let Some(cmake_toolchain_path) = cmake_toolchain_path {
-            rockchip_mpp_configure_cmd
-                .args(["--toolchain", &cmake_toolchain_path]);
-        }
-        let rockchip_mpp_configure_status = rockchip_mpp_configure_cmd
-            .status()
-            .expect("Failed to run rockchip-mpp configuration");
-        assert!(rockchip_mpp_configure_status.success(), "Error configuring rockchip-mpp");
-        let rockchip_mpp_build_status = Command::new("ninja")
-            .args([
-                "-C", rockchip_mpp_build_dir.as_str(),
-                "install",
             ])
-            .status()
-            .expect("Failed to run rockchip-mpp building");
-        assert!(rockchip_mpp_build_status.success(), "Error building rockchip-mpp");
+            .arg(format!("-Db_staticpic={}", env_vars.ffmpeg_pic))
+            .args(&env_vars.rockchip_rga_meson_opts);
+        if setup_build_dir(&mut rockchip_librga_setup_cmd, &rockchip_librga_build_dir, "rockchip-librga")? {
+            run_build_cmd(
+                "rockchip-librga/configure",
+                "configuring rockchip-librga",
+                Command::new("meson").args(["configure", rockchip_librga_build_dir.as_str()]),
+            )?;
+        }
+        run_build_cmd(
+            "rockchip-librga/build",
+            "building rockchip-librga",
+            Command::new("ninja")
+                .args(["-C", rockchip_librga_build_dir.as_str(), "install"])
+                .args(ninja_verbose_args),
+        )?;
+
+        let (rockchip_mpp_pkg_config_path, rockchip_mpp_lib_dir) =
+            if let Some(prebuilt_mpp) = &env_vars.rockchip_mpp_prebuilt {
+                if !env_vars.rockchip_mpp_cmake_opts.is_empty() {
+                    println!("cargo:warning=ROCKCHIP_MPP_CMAKE_OPTS is ignored with a prebuilt MPP");
+                }
+                eprintln!("Using prebuilt rockchip-mpp from {}", prebuilt_mpp.lib_dir);
+                report_progress("rockchip-mpp/build", "skipped");
+                // The shared libraries of the system must not be cleaned up
+                (write_prebuilt_mpp_pkg_config(prebuilt_mpp, &env_vars.out_dir), None)
+            } else {
+                let rockchip_mpp_install_dir = build_rockchip_mpp(
+                    env_vars,
+                    cmake_toolchain_path.as_deref(),
+                    ninja_verbose_args,
+                )?;
+                (
+                    rockchip_mpp_install_dir.join("lib").join("pkgconfig"),
+                    Some(rockchip_mpp_install_dir.join("lib")),
+                )
+            };
 
         (
             Some(format!(
                 "{libdrm_pkg_config_path}:{rockchip_mpp_pkg_config_path}:{rockchip_librga_pkg_config_path}"
             )),
-            vec!(
-                libdrm_install_dir.join("lib"),
-                rockchip_mpp_install_dir.join("lib"),
-            )
+            std::iter::once(libdrm_install_dir.join("lib"))
+                .chain(rockchip_mpp_lib_dir)
+                .collect::<Vec<_>>(),
         )
     } else {
         (None, vec!())
@@ -610,21 +1285,30 @@ fn build_ffmpeg(env_vars: &EnvVars) -> (PathBuf, String) {
         // right in the source directory
         let mut ffmpeg_git_clone_cmd = Command::new("git");
         ffmpeg_git_clone_cmd.args(["clone", "vendor/ffmpeg", ffmpeg_src_dir.as_str()]);
-        assert!(
-            ffmpeg_git_clone_cmd.status()
-                                .expect("Failed to run git clone for ffmpeg")
-                                .success(),
-            "Failed to clone ffmpeg sources"
-        );
+        run_build_cmd("ffmpeg/clone", "cloning FFmpeg sources", &mut ffmpeg_git_clone_cmd)?;
     }
     let ffmpeg_install_dir = ffmpeg_out_dir.join("install");
     let mut ffmpeg_configure_cmd = Command::new(
         ffmpeg_src_dir.join("configure")
     );
     ffmpeg_configure_cmd.current_dir(&ffmpeg_src_dir)
-        .arg(format!("--prefix={ffmpeg_install_dir}"))
+        .arg(format!("--prefix={ffmpeg_install_dir}"));
+    if env_vars.ffmpeg_license == FFmpegLicense::Gpl {
+        ffmpeg_configure_cmd.arg("--enable-gpl");
+    } else {
+        for opt in &env_vars.ffmpeg_configuration {
+            let gpl_only_component = opt.strip_prefix("--enable-")
+                .filter(|component| GPL_ONLY_COMPONENTS.contains(component));
+            if let Some(component) = gpl_only_component {
+                println!(
+                    "cargo:warning={component} requires a GPL build but FFMPEG_LICENSE is lgpl, \
+                    FFmpeg configuration will fail"
+                );
+            }
+        }
+    }
+    ffmpeg_configure_cmd
         .args([
-            "--enable-gpl",
             "--enable-version3",
             "--disable-iconv",
             "--disable-zlib",
@@ -636,6 +1320,38 @@ fn build_ffmpeg(env_vars: &EnvVars) -> (PathBuf, String) {
     if let Some(ffmpeg_cross_opts) = ffmpeg_cross_opts {
         ffmpeg_configure_cmd
             .args(&ffmpeg_cross_opts);
+    } else if let Some(ffmpeg_cpu) = &env_vars.ffmpeg_cpu {
+        ffmpeg_configure_cmd.arg(format!("--cpu={ffmpeg_cpu}"));
+    }
+    if env_vars.ffmpeg_pic {
+        ffmpeg_configure_cmd.arg("--enable-pic");
+    }
+    if env_vars.ffmpeg_link_mode == FFmpegLinkMode::Both {
+        ffmpeg_configure_cmd.args(["--enable-static", "--enable-shared"]);
+    }
+    match env_vars.ffmpeg_asm {
+        FFmpegAsm::On => {}
+        FFmpegAsm::Off => {
+            ffmpeg_configure_cmd.arg("--disable-asm");
+        }
+        FFmpegAsm::NoNeon => {
+            ffmpeg_configure_cmd.arg("--disable-neon");
+        }
+    }
+    match env_vars.ffmpeg_threads {
+        FFmpegThreads::On => {
+            ffmpeg_configure_cmd.arg("--enable-pthreads");
+        }
+        FFmpegThreads::Off => {
+            ffmpeg_configure_cmd.arg("--disable-pthreads");
+        }
+        FFmpegThreads::Auto => {
+            if let Ok(cross_toolchain_prefix) = env::var("CROSS_TOOLCHAIN_PREFIX") {
+                if !cross_toolchain_has_pthreads(&cross_toolchain_prefix) {
+                    ffmpeg_configure_cmd.arg("--disable-pthreads");
+                }
+            }
+        }
     }
     if let Some(ref ffmpeg_pkg_config_path) = ffmpeg_pkg_config_path {
         // Detect if we are inside a nix shell
@@ -652,28 +1368,91 @@ fn build_ffmpeg(env_vars: &EnvVars) -> (PathBuf, String) {
             );
         };
     }
+    // Passed before the user's flags so that they can still override them
+    if env_vars.ffmpeg_rockchip_mpp {
+        for opt in helpers::rkmpp_conflicting_flags(&env_vars.ffmpeg_configuration) {
+            println!(
+                "cargo:warning={opt} conflicts with FFMPEG_ROCKCHIP_MPP, \
+                some of the *_rkmpp codecs won't be available"
+            );
+        }
+        ffmpeg_configure_cmd.args(helpers::rkmpp_configuration(&env_vars.ffmpeg_configuration));
+        // The Rockchip libraries are built as static ones, so the configure checks
+        // must link their private dependencies (libdrm, libstdc++, ...) as well
+        let has_pkg_config_flags = env_vars.ffmpeg_configuration.iter()
+            .any(|opt| opt.starts_with("--pkg-config-flags="));
+        if env_vars.ffmpeg_link_mode.is_static() && !has_pkg_config_flags {
+            ffmpeg_configure_cmd.arg("--pkg-config-flags=--static");
+        }
+    }
+    // The pkg_config crate picks the same binary when probing the libraries
+    if let Some(pkg_config) = &env_vars.pkg_config {
+        ffmpeg_configure_cmd.arg(format!("--pkg-config={pkg_config}"));
+    }
+    let crate_flags = ffmpeg_configure_cmd.get_args()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    let conflicts = helpers::configuration_conflicts(&crate_flags, &env_vars.ffmpeg_configuration);
+    if env_vars.ffmpeg_configuration_strict && !conflicts.is_empty() {
+        return Err(BuildError::new("configuring FFmpeg", BuildErrorKind::ConflictingFlags(conflicts)));
+    }
+    for (flag, other_flag) in &conflicts {
+        println!("cargo:warning=FFmpeg configuration: {flag} conflicts with {other_flag}");
+    }
     ffmpeg_configure_cmd.args(&env_vars.ffmpeg_configuration);
-    assert!(
-        ffmpeg_configure_cmd.status()
-            .expect("Failed to run ffmpeg configuration")
-            .success(),
-        "Error configuring ffmpeg"
+    if env_vars.ffmpeg_verbose {
+        println!("cargo:warning=Configuring FFmpeg: {ffmpeg_configure_cmd:?}");
+    } else {
+        eprintln!("Configuring FFmpeg: {ffmpeg_configure_cmd:?}");
+    }
+    // FFmpeg's configure can't cache the results of its probes, so the whole run
+    // is skipped when the sources were configured with the same command and
+    // the same compiler
+    let configure_stamp_path = ffmpeg_src_dir.join(".rusty_ffmpeg_configure");
+    let configure_args = ffmpeg_configure_cmd.get_args()
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    let configure_fingerprint = format!(
+        "{ffmpeg_configure_cmd:?}\n{}",
+        compiler_fingerprint(helpers::configure_cc(&configure_args)),
     );
-    let ffmpeg_build_status = Command::new("make")
-        .args([
-            "-C", ffmpeg_src_dir.as_str(),
-            "-j", &env_vars.num_jobs,
-        ])
-        .status()
-        .expect("Failed to build ffmpeg");
-    assert!(ffmpeg_build_status.success(), "Error building ffmpeg");
-    let ffmpeg_install_status = Command::new("make")
-        .args(["-C", ffmpeg_src_dir.as_str()])
-        .arg("install")
-        .status()
-        .expect("Failed to run ffmpeg installation");
-    assert!(ffmpeg_install_status.success(), "Error installing ffmpeg");
+    let is_configured = env_vars.ffmpeg_configure_cache
+        && ffmpeg_src_dir.join("ffbuild").join("config.mak").exists()
+        && fs::read_to_string(&configure_stamp_path).is_ok_and(|stamp| stamp == configure_fingerprint);
+    if is_configured {
+        eprintln!("FFmpeg is configured with the same command, skipping configure");
+        report_progress("ffmpeg/configure", "skipped");
+    } else {
+        // An interrupted configure must not be taken for a finished one
+        let _ = fs::remove_file(&configure_stamp_path);
+        run_build_cmd("ffmpeg/configure", "configuring FFmpeg", &mut ffmpeg_configure_cmd)?;
+        fs::write(&configure_stamp_path, configure_fingerprint)
+            .map_err(|e| BuildError::io("configuring FFmpeg", &configure_stamp_path, e))?;
+    }
+    run_build_cmd(
+        "ffmpeg/build",
+        "building FFmpeg",
+        Command::new("make")
+            .args([
+                "-C", ffmpeg_src_dir.as_str(),
+                "-j", &env_vars.num_jobs,
+            ])
+            .args(make_verbose_args),
+    )?;
+    run_build_cmd(
+        "ffmpeg/install",
+        "installing FFmpeg",
+        Command::new("make")
+            .args(["-C", ffmpeg_src_dir.as_str()])
+            .arg("install"),
+    )?;
 
+    // The shared FFmpeg libraries are linked against the shared Rockchip ones
+    let dirs_to_cleanup_shared_libs = if env_vars.ffmpeg_link_mode == FFmpegLinkMode::Both {
+        vec!()
+    } else {
+        dirs_to_cleanup_shared_libs
+    };
     for cleanup_shared_libs_dir in &dirs_to_cleanup_shared_libs {
         // FIXME: Find out a way how to force a static linking
         for shared_lib_file_entry in fs::read_dir(cleanup_shared_libs_dir)
@@ -692,7 +1471,7 @@ fn build_ffmpeg(env_vars: &EnvVars) -> (PathBuf, String) {
         }
     }
 
-    (
+    Ok((
         ffmpeg_install_dir.join("include"),
         if let Some(ref ffmpeg_pkg_config_path) = ffmpeg_pkg_config_path {
             format!(
@@ -703,16 +1482,67 @@ fn build_ffmpeg(env_vars: &EnvVars) -> (PathBuf, String) {
         } else {
             ffmpeg_install_dir.join("lib").join("pkgconfig").as_str().to_string()
         }
-    )
+    ))
 }
 
 fn main() {
     let env_vars = EnvVars::init();
 
-    let (ffmpeg_include_dir, ffmpeg_pkg_config_path) = build_ffmpeg(&env_vars);
+    let (ffmpeg_include_dir, ffmpeg_pkg_config_path) =
+        if let Some(ffmpeg_install_dir) = &env_vars.ffmpeg_install_dir {
+            (
+                ffmpeg_install_dir.join("include"),
+                ffmpeg_install_dir.join("lib").join("pkgconfig").as_str().to_string(),
+            )
+        } else {
+            build_ffmpeg(&env_vars).unwrap_or_else(|e| {
+                eprintln!("error: {e}");
+                if e.is_command_failure() {
+                    eprintln!(
+                        "The output of the failed command is above. Set FFMPEG_VERBOSE=true \
+                        and run `cargo build -vv` to see the full build log."
+                    );
+                }
+                println!("cargo:warning={e}");
+                std::process::exit(1);
+            })
+        };
 
-    linking(&env_vars, &ffmpeg_include_dir, &ffmpeg_pkg_config_path);
+    let mut linking_summary = linking(&env_vars, &ffmpeg_include_dir, &ffmpeg_pkg_config_path);
+    // A prebuilt MPP may be outside of the default search paths
+    if let Some(prebuilt_mpp) = &env_vars.rockchip_mpp_prebuilt {
+        if env_vars.ffmpeg_install_dir.is_none() && env_vars.ffmpeg_rockchip_mpp {
+            println!("cargo:rustc-link-search=native={}", prebuilt_mpp.lib_dir);
+            linking_summary.lib_dirs.push(prebuilt_mpp.lib_dir.clone());
+        }
+    }
+    // Libraries the FFmpeg libs depend on but which their pkg-config files miss
+    for lib in &env_vars.ffmpeg_extra_libs {
+        println!("cargo:rustc-link-lib={}={lib}", env_vars.ffmpeg_link_mode);
+    }
+    #[cfg(not(target_os = "windows"))]
+    if let Some(ffmpeg_install_dir) = &env_vars.ffmpeg_install_dir {
+        if !env_vars.ffmpeg_link_mode.is_static() {
+            linking_shared_install_dir(ffmpeg_install_dir);
+        }
+    }
 
     // To link examples
     println!("cargo:rustc-link-arg=-lstdc++");
+
+    // `sws_scale_frame` appeared in libswscale 6 (FFmpeg 5.0), older versions
+    // only have `sws_scale`
+    let binding_path = env_vars.out_dir.join("binding.rs");
+    let binding = fs::read_to_string(&binding_path)
+        .unwrap_or_else(|e| panic!("Cannot read {binding_path}: {e}"));
+    println!("cargo:rustc-check-cfg=cfg(has_sws_scale_frame)");
+    if helpers::binding_constant(&binding, "LIBSWSCALE_VERSION_MAJOR").is_some_and(|major| major >= 6) {
+        println!("cargo:rustc-cfg=has_sws_scale_frame");
+    }
+
+    // Let the tests check which bindings were used
+    println!("cargo:rustc-env=RUSTY_FFMPEG_BINDINGS={}", linking_summary.bindings);
+    println!("cargo:rustc-env=RUSTY_FFMPEG_TARGET={}", env_vars.target);
+
+    println!("cargo:warning={linking_summary}");
 }
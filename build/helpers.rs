@@ -0,0 +1,1059 @@
+//! Helpers of the build script which don't depend on the build dependencies.
+//!
+//! The module is included both by `build.rs` and by the `build_helpers` test
+//! target, as the tests of a build script are never run otherwise.
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FFmpegLinkMode {
+    Static,
+    Dynamic,
+    /// Link the static libraries, but also build and install the shared ones
+    Both,
+}
+
+impl FFmpegLinkMode {
+    #[cfg_attr(target_os = "windows", allow(dead_code))]
+    pub fn is_static(&self) -> bool {
+        matches!(self, Self::Static | Self::Both)
+    }
+}
+
+impl std::str::FromStr for FFmpegLinkMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "static" => Ok(FFmpegLinkMode::Static),
+            "dynamic" => Ok(FFmpegLinkMode::Dynamic),
+            "both" => Ok(FFmpegLinkMode::Both),
+            _ => Err(format!(
+                "FFMPEG_LINK_MODE must be 'static', 'dynamic' or 'both', got '{value}'"
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for FFmpegLinkMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FFmpegLinkMode::Static | FFmpegLinkMode::Both => write!(f, "static"),
+            FFmpegLinkMode::Dynamic => write!(f, "dylib"),
+        }
+    }
+}
+
+/// Headers which are always bound: the codecs, the formats, the filters,
+/// the scalers and the parts of libavutil they need
+pub static CORE_HEADERS: &[&str] = &[
+    "libavcodec/avcodec.h",
+    "libavcodec/bsf.h",
+    "libavcodec/codec.h",
+    "libavcodec/codec_desc.h",
+    "libavcodec/codec_id.h",
+    "libavcodec/codec_par.h",
+    // "libavcodec/d3d11va.h",
+    "libavcodec/defs.h",
+    // "libavcodec/dxva2.h",
+    "libavcodec/packet.h",
+    // "libavcodec/qsv.h",
+    // "libavcodec/vdpau.h",
+    "libavcodec/version.h",
+    "libavcodec/version_major.h",
+    // "libavcodec/videotoolbox.h",
+    // "libavcodec/xvmc.h",
+    "libavdevice/avdevice.h",
+    "libavdevice/version.h",
+    "libavdevice/version_major.h",
+    "libavfilter/avfilter.h",
+    "libavfilter/buffersink.h",
+    "libavfilter/buffersrc.h",
+    "libavfilter/version.h",
+    "libavfilter/version_major.h",
+    "libavformat/avformat.h",
+    "libavformat/avio.h",
+    "libavformat/version.h",
+    "libavformat/version_major.h",
+    "libavutil/attributes.h",
+    "libavutil/avconfig.h",
+    "libavutil/avutil.h",
+    "libavutil/buffer.h",
+    "libavutil/channel_layout.h",
+    "libavutil/common.h",
+    "libavutil/dict.h",
+    "libavutil/error.h",
+    "libavutil/frame.h",
+    "libavutil/hdr_dynamic_metadata.h",
+    "libavutil/hwcontext.h",
+    // "libavutil/hwcontext_cuda.h",
+    // "libavutil/hwcontext_d3d11va.h",
+    "libavutil/hwcontext_drm.h",
+    // "libavutil/hwcontext_dxva2.h",
+    // "libavutil/hwcontext_mediacodec.h",
+    // "libavutil/hwcontext_opencl.h",
+    // "libavutil/hwcontext_qsv.h",
+    // "libavutil/hwcontext_vaapi.h",
+    // "libavutil/hwcontext_vdpau.h",
+    // "libavutil/hwcontext_videotoolbox.h",
+    // "libavutil/hwcontext_vulkan.h",
+    "libavutil/imgutils.h",
+    "libavutil/log.h",
+    "libavutil/macros.h",
+    "libavutil/mastering_display_metadata.h",
+    "libavutil/mathematics.h",
+    "libavutil/mem.h",
+    "libavutil/motion_vector.h",
+    "libavutil/opt.h",
+    "libavutil/parseutils.h",
+    "libavutil/pixdesc.h",
+    "libavutil/pixfmt.h",
+    "libavutil/rational.h",
+    "libavutil/samplefmt.h",
+    "libavutil/version.h",
+    "libswresample/swresample.h",
+    "libswresample/version.h",
+    "libswresample/version_major.h",
+    "libswscale/swscale.h",
+    "libswscale/version.h",
+    "libswscale/version_major.h",
+];
+
+/// The rest of libavcodec and libavutil (parsers of a few bitstreams, FIFOs,
+/// stereo3d, spherical, timecodes, ...), bound with the `full-bindings` feature
+pub static EXTRA_HEADERS: &[&str] = &[
+    "libavcodec/ac3_parser.h",
+    "libavcodec/adts_parser.h",
+    "libavcodec/avdct.h",
+    "libavcodec/avfft.h",
+    "libavcodec/dirac.h",
+    "libavcodec/dv_profile.h",
+    "libavcodec/jni.h",
+    "libavcodec/mediacodec.h",
+    "libavcodec/vorbis_parser.h",
+    "libavutil/ambient_viewing_environment.h",
+    "libavutil/audio_fifo.h",
+    "libavutil/avassert.h",
+    "libavutil/avstring.h",
+    "libavutil/base64.h",
+    "libavutil/bprint.h",
+    "libavutil/bswap.h",
+    "libavutil/cpu.h",
+    "libavutil/crc.h",
+    "libavutil/csp.h",
+    "libavutil/detection_bbox.h",
+    "libavutil/display.h",
+    "libavutil/dovi_meta.h",
+    "libavutil/downmix_info.h",
+    "libavutil/encryption_info.h",
+    "libavutil/eval.h",
+    "libavutil/executor.h",
+    "libavutil/ffversion.h",
+    "libavutil/fifo.h",
+    "libavutil/file.h",
+    "libavutil/film_grain_params.h",
+    "libavutil/hdr_dynamic_vivid_metadata.h",
+    "libavutil/intfloat.h",
+    "libavutil/intreadwrite.h",
+    "libavutil/lfg.h",
+    "libavutil/lzo.h",
+    "libavutil/pixelutils.h",
+    "libavutil/random_seed.h",
+    "libavutil/replaygain.h",
+    "libavutil/spherical.h",
+    "libavutil/stereo3d.h",
+    "libavutil/threadmessage.h",
+    "libavutil/time.h",
+    "libavutil/timecode.h",
+    "libavutil/timestamp.h",
+    "libavutil/tree.h",
+    "libavutil/tx.h",
+    "libavutil/uuid.h",
+    "libavutil/video_enc_params.h",
+    "libavutil/video_hint.h",
+];
+
+/// Ciphers, bound with the `crypto` feature
+pub static CRYPTO_HEADERS: &[&str] = &[
+    "libavutil/aes.h",
+    "libavutil/aes_ctr.h",
+    "libavutil/blowfish.h",
+    "libavutil/camellia.h",
+    "libavutil/cast5.h",
+    "libavutil/des.h",
+    "libavutil/rc4.h",
+    "libavutil/tea.h",
+    "libavutil/twofish.h",
+    "libavutil/xtea.h",
+];
+
+/// Hash functions and checksums, bound with the `hash` feature
+pub static HASH_HEADERS: &[&str] = &[
+    "libavutil/adler32.h",
+    "libavutil/hash.h",
+    "libavutil/hmac.h",
+    "libavutil/md5.h",
+    "libavutil/murmur3.h",
+    "libavutil/ripemd.h",
+    "libavutil/sha.h",
+    "libavutil/sha512.h",
+];
+
+/// The headers to generate bindings for, the core ones and the ones of the
+/// enabled features.
+pub fn headers(full_bindings: bool, crypto: bool, hash: bool) -> Vec<&'static str> {
+    let mut headers = CORE_HEADERS.to_vec();
+    if full_bindings {
+        headers.extend_from_slice(EXTRA_HEADERS);
+    }
+    if crypto {
+        headers.extend_from_slice(CRYPTO_HEADERS);
+    }
+    if hash {
+        headers.extend_from_slice(HASH_HEADERS);
+    }
+    headers
+}
+
+/// Configure flags enabling the `*_rkmpp` codecs, added when `FFMPEG_ROCKCHIP_MPP` is set
+pub static RKMPP_CONFIGURATION: &[&str] = &[
+    "--enable-libdrm",
+    "--enable-rkmpp",
+    "--enable-rkrga",
+    "--enable-decoder=h264_rkmpp",
+    "--enable-decoder=hevc_rkmpp",
+    "--enable-decoder=mjpeg_rkmpp",
+    "--enable-encoder=h264_rkmpp",
+    "--enable-encoder=hevc_rkmpp",
+    "--enable-encoder=mjpeg_rkmpp",
+];
+
+/// Returns the rkmpp flags missing from the user's configuration.
+pub fn rkmpp_configuration(configuration: &[String]) -> Vec<String> {
+    RKMPP_CONFIGURATION
+        .iter()
+        .filter(|flag| !configuration.iter().any(|opt| opt == *flag))
+        .map(|flag| flag.to_string())
+        .collect()
+}
+
+/// Returns the user's flags which disable some of the rkmpp flags.
+pub fn rkmpp_conflicting_flags(configuration: &[String]) -> Vec<&String> {
+    configuration
+        .iter()
+        .filter(|opt| {
+            let Some(disabled) = opt.strip_prefix("--disable-") else {
+                return false;
+            };
+            RKMPP_CONFIGURATION
+                .iter()
+                .filter_map(|flag| flag.strip_prefix("--enable-"))
+                .any(|component| {
+                    // --disable-decoders disables --enable-decoder=h264_rkmpp as well
+                    component == disabled
+                        || component
+                            .split_once('=')
+                            .is_some_and(|(kind, _)| disabled == format!("{kind}s"))
+                })
+        })
+        .collect()
+}
+
+/// Maps a Cargo target OS to the `--target-os` value of FFmpeg's configure.
+/// Most of the names are the same.
+pub fn ffmpeg_target_os(target_os: &str, target_env: &str) -> String {
+    match (target_os, target_env) {
+        ("macos" | "ios" | "tvos" | "watchos", _) => "darwin",
+        ("windows", "gnu") => "mingw32",
+        ("windows", _) => "win64",
+        ("solaris" | "illumos", _) => "sunos",
+        ("nto", _) => "qnx",
+        (target_os, _) => target_os,
+    }
+    .to_string()
+}
+
+/// Checks that the `(source_dir, entry_point)` sources under `root` are checked
+/// out. A submodule which is not checked out is an empty dir.
+pub fn check_vendored_sources(
+    root: &std::path::Path,
+    sources: &[(&str, &str)],
+) -> Result<(), String> {
+    for (source_dir, entry_point) in sources {
+        if !root.join(source_dir).join(entry_point).exists() {
+            return Err(format!(
+                "{source_dir}/{entry_point} not found, the {source_dir} submodule is not checked out: \
+                run `git submodule update --init --recursive`"
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Value of an integer constant of generated bindings, e.g.
+/// `LIBSWSCALE_VERSION_MAJOR`, `None` if it is absent.
+pub fn binding_constant(binding: &str, name: &str) -> Option<u64> {
+    let prefix = format!("pub const {name}: ");
+    binding.lines().find_map(|line| {
+        let (_, value) = line.trim_start().strip_prefix(&prefix)?.split_once(" = ")?;
+        value.strip_suffix(';')?.parse().ok()
+    })
+}
+
+/// The C compiler FFmpeg's `configure` runs with the given flags: the one of
+/// the last `--cc` flag, `gcc` by default.
+pub fn configure_cc(args: &[String]) -> &str {
+    args.iter()
+        .rev()
+        .find_map(|arg| arg.strip_prefix("--cc="))
+        .unwrap_or("gcc")
+}
+
+/// Names of the functions, types, constants and statics of generated bindings.
+pub fn binding_symbols(binding: &str) -> std::collections::BTreeSet<String> {
+    const ITEM_PREFIXES: &[&str] = &[
+        "pub fn ",
+        "pub struct ",
+        "pub union ",
+        "pub enum ",
+        "pub type ",
+        "pub const ",
+        "pub static mut ",
+        "pub static ",
+    ];
+    binding
+        .lines()
+        .filter_map(|line| {
+            let line = line.trim_start();
+            let item = ITEM_PREFIXES
+                .iter()
+                .find_map(|prefix| line.strip_prefix(prefix))?;
+            let name_len = item
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(item.len());
+            Some(item[..name_len].to_string())
+        })
+        .collect()
+}
+
+/// FFmpeg libraries and the kinds of the components they contain
+pub static LIBRARY_COMPONENTS: &[(&str, &[&str])] = &[
+    (
+        "avcodec",
+        &["decoder", "encoder", "parser", "bsf", "hwaccel"],
+    ),
+    ("avformat", &["muxer", "demuxer", "protocol"]),
+    ("avfilter", &["filter"]),
+    ("avdevice", &["indev", "outdev"]),
+];
+
+/// Returns the pairs of contradicting `configure` flags: a flag of the user's
+/// configuration which is both enabled and disabled (e.g. `--enable-static`
+/// and `--disable-static`), and a disabled library whose components are
+/// enabled (e.g. `--disable-avcodec` and `--enable-decoder=h264`).
+///
+/// The crate's own flags are only checked for the disabled libraries, as
+/// the user's flags are meant to override them.
+pub fn configuration_conflicts(
+    crate_flags: &[String],
+    user_flags: &[String],
+) -> Vec<(String, String)> {
+    let mut conflicts = vec![];
+    for flag in user_flags {
+        let Some(component) = flag.strip_prefix("--enable-") else {
+            continue;
+        };
+        let disable_flag = format!("--disable-{component}");
+        let is_reported = conflicts.iter().any(|(enable_flag, _)| enable_flag == flag);
+        if user_flags.contains(&disable_flag) && !is_reported {
+            conflicts.push((flag.clone(), disable_flag));
+        }
+    }
+
+    let flags = crate_flags.iter().chain(user_flags).collect::<Vec<_>>();
+    for (library, kinds) in LIBRARY_COMPONENTS {
+        let disable_flag = format!("--disable-{library}");
+        if !flags.iter().any(|flag| **flag == disable_flag) {
+            continue;
+        }
+        let enabled_component = flags.iter().find(|flag| {
+            flag.strip_prefix("--enable-")
+                .and_then(|component| component.split_once('='))
+                .is_some_and(|(kind, _)| kinds.contains(&kind))
+        });
+        if let Some(enabled_component) = enabled_component {
+            conflicts.push((disable_flag, enabled_component.to_string()));
+        }
+    }
+    conflicts
+}
+
+/// Flags of the `default-encoders` feature: the common software encoders and
+/// muxers to write their output into files
+pub static DEFAULT_ENCODERS_CONFIGURATION: &[&str] = &[
+    "--enable-encoder=mjpeg",
+    "--enable-encoder=rawvideo",
+    "--enable-encoder=pcm_s16le",
+    "--enable-muxer=mp4",
+    "--enable-muxer=matroska",
+    "--enable-muxer=rawvideo",
+    "--enable-protocol=file",
+];
+
+/// Flags of the `default-rkmpp` feature, on top of the rkmpp codecs (see
+/// [`RKMPP_CONFIGURATION`]): the parsers, demuxers and muxers to read and
+/// write raw and MP4 streams, and the filters to scale frames with RGA
+pub static DEFAULT_RKMPP_CONFIGURATION: &[&str] = &[
+    "--enable-parser=h264",
+    "--enable-parser=hevc",
+    "--enable-parser=mjpeg",
+    "--enable-demuxer=h264",
+    "--enable-demuxer=hevc",
+    "--enable-demuxer=mov",
+    "--enable-muxer=h264",
+    "--enable-muxer=hevc",
+    "--enable-muxer=mp4",
+    "--enable-filter=hwupload",
+    "--enable-filter=hwdownload",
+    "--enable-filter=scale_rkrga",
+    "--enable-protocol=file",
+];
+
+/// Configure flags of the enabled `default-encoders` and `default-rkmpp`
+/// features, without duplicates.
+pub fn default_configuration(default_encoders: bool, default_rkmpp: bool) -> Vec<String> {
+    let mut flags: Vec<String> = vec![];
+    let feature_flags = [
+        (default_encoders, DEFAULT_ENCODERS_CONFIGURATION),
+        (default_rkmpp, DEFAULT_RKMPP_CONFIGURATION),
+    ];
+    for (_, configuration) in feature_flags.iter().filter(|(enabled, _)| *enabled) {
+        for flag in configuration.iter() {
+            if !flags.iter().any(|f| f == flag) {
+                flags.push(flag.to_string());
+            }
+        }
+    }
+    flags
+}
+
+/// Rust version the bindings are generated for unless `FFMPEG_RUST_TARGET`
+/// overrides it
+pub const DEFAULT_RUST_TARGET: &str = "1.68";
+
+/// Parses a stable Rust version like `1.68` or `1.68.2` into its minor and
+/// patch versions.
+pub fn parse_rust_version(value: &str) -> Result<(u64, u64), String> {
+    let value = value.trim();
+    let parts = value.split('.').collect::<Vec<_>>();
+    let (minor, patch) = match parts.as_slice() {
+        ["1", minor] => (*minor, "0"),
+        ["1", minor, patch] => (*minor, *patch),
+        _ => return Err("expected a stable Rust version like `1.68` or `1.68.0`".to_string()),
+    };
+    let minor = minor
+        .parse()
+        .map_err(|e| format!("invalid minor version `{minor}`: {e}"))?;
+    let patch = patch
+        .parse()
+        .map_err(|e| format!("invalid patch version `{patch}`: {e}"))?;
+    Ok((minor, patch))
+}
+
+/// Quotes a meson string literal.
+pub fn meson_string(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Quotes a meson array of strings.
+pub fn meson_array<S: AsRef<str>>(values: &[S]) -> String {
+    let values = values
+        .iter()
+        .map(|v| meson_string(v.as_ref()))
+        .collect::<Vec<_>>();
+    format!("[{}]", values.join(", "))
+}
+
+/// Meson `cpu_family` and `cpu` of a Cargo `target_arch`
+/// (`CARGO_CFG_TARGET_ARCH`).
+pub fn meson_host_cpu(target_arch: &str) -> (&str, &str) {
+    match target_arch {
+        // Rust targets the ARMv7 Rockchip SoCs (e.g. RV1126) as `arm`
+        "arm" => ("arm", "armv7"),
+        "x86" => ("x86", "i686"),
+        "powerpc" => ("ppc", "ppc"),
+        "powerpc64" => ("ppc64", "ppc64"),
+        _ => (target_arch, target_arch),
+    }
+}
+
+/// Renders the meson cross file of the vendored libraries.
+///
+/// The host machine is the one of `target_arch` (`CARGO_CFG_TARGET_ARCH`),
+/// `compiler_args` are appended to the compilers, `c_args` and `c_link_args`
+/// go to the built-in options and `pkg_config_libdir` (e.g. the pkgconfig dir
+/// of the target sysroot) to the properties.
+pub fn meson_cross_file_content(
+    cross_toolchain_prefix: &str,
+    target_arch: &str,
+    compiler_args: &[&str],
+    c_args: &[String],
+    c_link_args: &[String],
+    pkg_config_libdir: Option<&str>,
+) -> String {
+    // Meson options given with -D (e.g. cpp_args of rockchip-librga) replace
+    // the ones of the cross file, so the flags go along with the compilers
+    let meson_compiler = |compiler: &str| {
+        let mut args = vec![format!("{cross_toolchain_prefix}{compiler}")];
+        args.extend(compiler_args.iter().map(|arg| arg.to_string()));
+        meson_array(&args)
+    };
+    let meson_c = meson_compiler("gcc");
+    let meson_cpp = meson_compiler("g++");
+    let (cpu_family, cpu) = meson_host_cpu(target_arch);
+    let mut content = format!(
+        "[binaries]\n\
+        c = {meson_c}\n\
+        cpp = {meson_cpp}\n\
+        ar = '{cross_toolchain_prefix}ar'\n\
+        strip = '{cross_toolchain_prefix}strip'\n\
+        \n\
+        [host_machine]\n\
+        system = 'linux'\n\
+        cpu_family = '{cpu_family}'\n\
+        cpu = '{cpu}'\n\
+        endian = 'little'\n\
+        \n\
+        [properties]\n\
+        needs_exe_wrapper = true\n"
+    );
+    if let Some(pkg_config_libdir) = pkg_config_libdir {
+        content.push_str(&format!(
+            "pkg_config_libdir = {}\n",
+            meson_string(pkg_config_libdir)
+        ));
+    }
+    if !c_args.is_empty() || !c_link_args.is_empty() {
+        content.push_str("\n[built-in options]\n");
+        if !c_args.is_empty() {
+            content.push_str(&format!("c_args = {}\n", meson_array(c_args)));
+        }
+        if !c_link_args.is_empty() {
+            content.push_str(&format!("c_link_args = {}\n", meson_array(c_link_args)));
+        }
+    }
+    content
+}
+
+/// Number of jobs for `make`: the one of Cargo in `NUM_JOBS`, or the CPU count
+/// when the build script is not driven by Cargo.
+pub fn num_jobs(num_jobs_env: Option<String>) -> String {
+    num_jobs_env.unwrap_or_else(|| {
+        std::thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+            .to_string()
+    })
+}
+
+/// Parses a configuration file with one configure flag per line.
+/// Blank lines and lines starting with `#` are skipped.
+///
+/// A line is taken as is, so a flag with spaces doesn't need quoting: quotes
+/// would be passed to configure along with the value.
+pub fn parse_configuration_file(content: &str) -> Vec<String> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect()
+}
+
+/// Flags of `FFMPEG_CONFIGURATION_FILE` followed by the space-separated ones of
+/// `FFMPEG_CONFIGURATION`, so the inline flags take precedence.
+pub fn configuration_flags(file_content: Option<&str>, configuration: Option<&str>) -> Vec<String> {
+    let mut flags = file_content
+        .map(parse_configuration_file)
+        .unwrap_or_default();
+    if let Some(configuration) = configuration {
+        flags.extend(
+            configuration
+                .split(' ')
+                .filter(|v| !v.is_empty())
+                .map(String::from),
+        );
+    }
+    flags
+}
+
+/// Parses library names from the `Libs.private` field of a `.pc` file.
+/// Flags other than `-l` and `-pthread` (e.g. `-L`) are skipped.
+#[cfg_attr(target_os = "windows", allow(dead_code))]
+pub fn parse_private_libs(pc_content: &str) -> Vec<String> {
+    let Some(libs_private) = pc_content
+        .lines()
+        .find_map(|line| line.strip_prefix("Libs.private:"))
+    else {
+        return vec![];
+    };
+    libs_private
+        .split_whitespace()
+        .filter_map(|flag| {
+            if flag == "-pthread" {
+                Some("pthread".to_string())
+            } else {
+                flag.strip_prefix("-l").map(String::from)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_num_jobs() {
+        assert_eq!(num_jobs(Some("3".to_string())), "3");
+
+        let num_jobs = num_jobs(None).parse::<usize>().unwrap();
+        assert!(num_jobs >= 1);
+        if let Ok(parallelism) = std::thread::available_parallelism() {
+            assert_eq!(num_jobs, parallelism.get());
+        }
+    }
+
+    #[test]
+    fn test_parse_configuration_file() {
+        let content = "
+            # Decoders
+            --enable-decoder=h264
+
+              --enable-decoder=hevc  
+            #--enable-decoder=mjpeg
+            --extra-cflags=-O2 -g
+            --extra-ldflags=\"-L/opt/lib\"
+        ";
+        assert_eq!(
+            parse_configuration_file(content),
+            [
+                "--enable-decoder=h264",
+                "--enable-decoder=hevc",
+                "--extra-cflags=-O2 -g",
+                "--extra-ldflags=\"-L/opt/lib\"",
+            ],
+        );
+        assert!(parse_configuration_file("").is_empty());
+        assert!(parse_configuration_file("# only a comment\n\n").is_empty());
+    }
+
+    #[test]
+    fn test_configuration_flags() {
+        let file_content = "--enable-decoder=h264\n--disable-decoder=hevc\n";
+        // The inline flags come last, so configure applies them over the file
+        assert_eq!(
+            configuration_flags(
+                Some(file_content),
+                Some(" --enable-decoder=hevc  --enable-muxer=mp4")
+            ),
+            [
+                "--enable-decoder=h264",
+                "--disable-decoder=hevc",
+                "--enable-decoder=hevc",
+                "--enable-muxer=mp4"
+            ],
+        );
+        assert_eq!(
+            configuration_flags(Some(file_content), None),
+            ["--enable-decoder=h264", "--disable-decoder=hevc"]
+        );
+        assert_eq!(
+            configuration_flags(None, Some("--enable-decoder=h264")),
+            ["--enable-decoder=h264"]
+        );
+        assert!(configuration_flags(None, None).is_empty());
+    }
+
+    #[test]
+    fn test_parse_private_libs() {
+        let pc_content = "\
+prefix=/usr/local
+libdir=${prefix}/lib
+
+Name: libavcodec
+Version: 61.19.100
+Requires.private: libswresample >= 5.3.100, libavutil >= 59.39.100
+Libs: -L${libdir}  -lavcodec
+Libs.private: -L/opt/rockchip/lib -lrockchip_mpp -pthread -lm -latomic -Wl,--as-needed -ldrm
+Cflags: -I${includedir}
+";
+        assert_eq!(
+            parse_private_libs(pc_content),
+            ["rockchip_mpp", "pthread", "m", "atomic", "drm"]
+        );
+        assert!(parse_private_libs("Libs.private:\n").is_empty());
+        assert!(parse_private_libs("Libs: -lavutil\n").is_empty());
+    }
+
+    #[test]
+    fn test_link_mode() {
+        assert_eq!("static".parse(), Ok(FFmpegLinkMode::Static));
+        assert_eq!("dynamic".parse(), Ok(FFmpegLinkMode::Dynamic));
+        assert_eq!("both".parse(), Ok(FFmpegLinkMode::Both));
+        assert_eq!(
+            "dylib".parse::<FFmpegLinkMode>(),
+            Err("FFMPEG_LINK_MODE must be 'static', 'dynamic' or 'both', got 'dylib'".to_string()),
+        );
+        assert!(FFmpegLinkMode::Both.is_static());
+        assert!(!FFmpegLinkMode::Dynamic.is_static());
+        // The link kind of `cargo:rustc-link-lib`
+        assert_eq!(FFmpegLinkMode::Both.to_string(), "static");
+        assert_eq!(FFmpegLinkMode::Dynamic.to_string(), "dylib");
+    }
+
+    #[test]
+    fn test_headers() {
+        let core = headers(false, false, false);
+        assert!(core.contains(&"libavcodec/avcodec.h"));
+        // The optional headers are only bound with their features
+        for header in [
+            "libavutil/lfg.h",
+            "libavutil/tree.h",
+            "libavutil/murmur3.h",
+            "libavutil/aes.h",
+        ] {
+            assert!(!core.contains(&header), "{header} is a core header");
+        }
+        assert!(headers(true, false, false).contains(&"libavutil/lfg.h"));
+        assert!(headers(true, false, false).contains(&"libavutil/tree.h"));
+        assert!(headers(false, true, false).contains(&"libavutil/aes.h"));
+        assert!(headers(false, false, true).contains(&"libavutil/murmur3.h"));
+        assert!(!headers(true, false, false).contains(&"libavutil/murmur3.h"));
+
+        let all = headers(true, true, true);
+        let unique = all.iter().collect::<std::collections::BTreeSet<_>>();
+        assert_eq!(unique.len(), all.len(), "A header is in several lists");
+    }
+
+    #[test]
+    fn test_rkmpp_configuration() {
+        let flags = |flags: &[&str]| {
+            flags
+                .iter()
+                .map(|flag| flag.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(rkmpp_configuration(&[]), flags(RKMPP_CONFIGURATION));
+        // The flags of the user are not repeated
+        let configuration = flags(&[
+            "--enable-rkmpp",
+            "--enable-decoder=h264_rkmpp",
+            "--enable-decoder=h264",
+        ]);
+        assert_eq!(
+            rkmpp_configuration(&configuration),
+            flags(&[
+                "--enable-libdrm",
+                "--enable-rkrga",
+                "--enable-decoder=hevc_rkmpp",
+                "--enable-decoder=mjpeg_rkmpp",
+                "--enable-encoder=h264_rkmpp",
+                "--enable-encoder=hevc_rkmpp",
+                "--enable-encoder=mjpeg_rkmpp",
+            ]),
+        );
+    }
+
+    #[test]
+    fn test_rkmpp_conflicting_flags() {
+        let configuration = [
+            "--disable-rkrga",
+            "--disable-decoders",
+            "--disable-encoder=hevc_rkmpp",
+            "--disable-muxers",
+            "--disable-decoder=h264",
+            "--enable-libdrm",
+        ]
+        .map(String::from);
+        assert_eq!(
+            rkmpp_conflicting_flags(&configuration),
+            [&configuration[0], &configuration[1], &configuration[2]],
+        );
+        assert!(rkmpp_conflicting_flags(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_ffmpeg_target_os() {
+        assert_eq!(ffmpeg_target_os("linux", "gnu"), "linux");
+        assert_eq!(ffmpeg_target_os("android", ""), "android");
+        assert_eq!(ffmpeg_target_os("macos", ""), "darwin");
+        assert_eq!(ffmpeg_target_os("ios", ""), "darwin");
+        assert_eq!(ffmpeg_target_os("windows", "gnu"), "mingw32");
+        assert_eq!(ffmpeg_target_os("windows", "msvc"), "win64");
+        assert_eq!(ffmpeg_target_os("illumos", ""), "sunos");
+        assert_eq!(ffmpeg_target_os("nto", "nto71"), "qnx");
+    }
+
+    #[test]
+    fn test_check_vendored_sources() {
+        let root = std::env::temp_dir().join(format!(
+            "rusty_ffmpeg_vendored_sources_{}",
+            std::process::id()
+        ));
+        let sources = [
+            ("vendor/ffmpeg", "configure"),
+            ("vendor/libdrm", "meson.build"),
+        ];
+        let missing_error = |source_dir: &str, entry_point: &str| {
+            Err(format!(
+            "{source_dir}/{entry_point} not found, the {source_dir} submodule is not checked out: \
+            run `git submodule update --init --recursive`"
+        ))
+        };
+
+        // An empty dir of a submodule which is not checked out
+        std::fs::create_dir_all(root.join("vendor/ffmpeg")).unwrap();
+        assert_eq!(
+            check_vendored_sources(&root, &sources),
+            missing_error("vendor/ffmpeg", "configure")
+        );
+
+        std::fs::write(root.join("vendor/ffmpeg/configure"), "").unwrap();
+        assert_eq!(
+            check_vendored_sources(&root, &sources),
+            missing_error("vendor/libdrm", "meson.build")
+        );
+
+        std::fs::create_dir_all(root.join("vendor/libdrm")).unwrap();
+        std::fs::write(root.join("vendor/libdrm/meson.build"), "").unwrap();
+        assert_eq!(check_vendored_sources(&root, &sources), Ok(()));
+
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn test_binding_symbols() {
+        let binding = "\
+pub const LIBAVCODEC_VERSION_MAJOR: u32 = 61;
+pub type AVPixelFormat = ::std::os::raw::c_int;
+#[repr(C)]
+pub struct AVRational {
+    pub num: ::std::os::raw::c_int,
+    pub den: ::std::os::raw::c_int,
+}
+pub union AVOptionValue {
+    pub i64_: i64,
+}
+extern \"C\" {
+    pub static mut av_log_level: ::std::os::raw::c_int;
+    pub fn av_log_set_level(level: ::std::os::raw::c_int);
+}
+";
+        assert_eq!(
+            binding_symbols(binding).into_iter().collect::<Vec<_>>(),
+            [
+                "AVOptionValue",
+                "AVPixelFormat",
+                "AVRational",
+                "LIBAVCODEC_VERSION_MAJOR",
+                "av_log_level",
+                "av_log_set_level"
+            ],
+        );
+        // The fields are not symbols
+        assert!(binding_symbols("    pub num: i32,\n").is_empty());
+    }
+
+    #[test]
+    fn test_binding_constant() {
+        let binding = "\
+pub const LIBSWSCALE_VERSION_MAJOR: u32 = 8;
+pub const LIBSWSCALE_VERSION_MINOR: u32 = 3;
+pub const AV_NOPTS_VALUE: i64 = -9223372036854775808;
+";
+        assert_eq!(
+            binding_constant(binding, "LIBSWSCALE_VERSION_MAJOR"),
+            Some(8)
+        );
+        assert_eq!(
+            binding_constant(binding, "LIBSWSCALE_VERSION_MINOR"),
+            Some(3)
+        );
+        assert_eq!(binding_constant(binding, "LIBSWSCALE_VERSION"), None);
+        assert_eq!(binding_constant(binding, "AV_NOPTS_VALUE"), None);
+    }
+
+    #[test]
+    fn test_configure_cc() {
+        let args = |args: &[&str]| args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>();
+        assert_eq!(configure_cc(&args(&["--enable-gpl"])), "gcc");
+        assert_eq!(
+            configure_cc(&args(&["--cc=aarch64-linux-gnu-gcc", "--enable-gpl"])),
+            "aarch64-linux-gnu-gcc"
+        );
+        // The user's flags come last and override the crate's ones
+        assert_eq!(
+            configure_cc(&args(&[
+                "--cc=aarch64-linux-gnu-gcc",
+                "--cc=/opt/cc/bin/clang"
+            ])),
+            "/opt/cc/bin/clang"
+        );
+    }
+
+    #[test]
+    fn test_configuration_conflicts() {
+        let flags = |flags: &[&str]| {
+            flags
+                .iter()
+                .map(|flag| flag.to_string())
+                .collect::<Vec<_>>()
+        };
+        let conflicts = |conflicts: &[(&str, &str)]| {
+            conflicts
+                .iter()
+                .map(|(flag, other_flag)| (flag.to_string(), other_flag.to_string()))
+                .collect::<Vec<_>>()
+        };
+        let crate_flags = flags(&["--enable-gpl", "--disable-everything", "--enable-pic"]);
+
+        assert!(configuration_conflicts(&crate_flags, &[]).is_empty());
+        assert!(
+            configuration_conflicts(&crate_flags, &flags(&["--enable-decoder=h264"])).is_empty()
+        );
+
+        // The user's flags override the crate's ones
+        assert!(configuration_conflicts(&crate_flags, &flags(&["--disable-pic"])).is_empty());
+        // An enabled and disabled flag is reported once
+        let user_flags = flags(&[
+            "--enable-static",
+            "--disable-static",
+            "--enable-static",
+            "--enable-shared",
+        ]);
+        assert_eq!(
+            configuration_conflicts(&crate_flags, &user_flags),
+            conflicts(&[("--enable-static", "--disable-static")]),
+        );
+
+        // Components of a disabled library
+        let user_flags = flags(&[
+            "--disable-avformat",
+            "--enable-decoder=h264",
+            "--enable-muxer=mp4",
+        ]);
+        assert_eq!(
+            configuration_conflicts(&crate_flags, &user_flags),
+            conflicts(&[("--disable-avformat", "--enable-muxer=mp4")]),
+        );
+        let user_flags = flags(&["--disable-avdevice"]);
+        let crate_flags = flags(&["--enable-indev=v4l2"]);
+        assert_eq!(
+            configuration_conflicts(&crate_flags, &user_flags),
+            conflicts(&[("--disable-avdevice", "--enable-indev=v4l2")]),
+        );
+    }
+
+    #[test]
+    fn test_default_configuration() {
+        assert!(default_configuration(false, false).is_empty());
+        assert_eq!(
+            default_configuration(true, false),
+            DEFAULT_ENCODERS_CONFIGURATION
+        );
+        assert_eq!(
+            default_configuration(false, true),
+            DEFAULT_RKMPP_CONFIGURATION
+        );
+
+        // The flags of both features, without repeating the shared ones
+        let flags = default_configuration(true, true);
+        assert_eq!(
+            flags[..DEFAULT_ENCODERS_CONFIGURATION.len()],
+            *DEFAULT_ENCODERS_CONFIGURATION
+        );
+        for flag in DEFAULT_RKMPP_CONFIGURATION {
+            assert_eq!(flags.iter().filter(|f| f == flag).count(), 1, "{flag}");
+        }
+        assert_eq!(
+            flags.iter().filter(|f| *f == "--enable-muxer=mp4").count(),
+            1
+        );
+        assert!(flags.contains(&"--enable-encoder=mjpeg".to_string()));
+        assert!(flags.contains(&"--enable-filter=scale_rkrga".to_string()));
+    }
+
+    #[test]
+    fn test_parse_rust_version() {
+        assert_eq!(parse_rust_version(DEFAULT_RUST_TARGET), Ok((68, 0)));
+        assert_eq!(parse_rust_version("1.70"), Ok((70, 0)));
+        assert_eq!(parse_rust_version(" 1.68.2\n"), Ok((68, 2)));
+
+        let expected_version =
+            Err("expected a stable Rust version like `1.68` or `1.68.0`".to_string());
+        assert_eq!(parse_rust_version(""), expected_version);
+        assert_eq!(parse_rust_version("1"), expected_version);
+        assert_eq!(parse_rust_version("2.0"), expected_version);
+        assert_eq!(parse_rust_version("1.68.0.1"), expected_version);
+        assert_eq!(parse_rust_version("nightly"), expected_version);
+        assert!(parse_rust_version("1.x")
+            .unwrap_err()
+            .starts_with("invalid minor version `x`"));
+        assert!(parse_rust_version("1.68.beta")
+            .unwrap_err()
+            .starts_with("invalid patch version `beta`"));
+    }
+
+    #[test]
+    fn test_meson_cross_file_content() {
+        let content = meson_cross_file_content(
+            "aarch64-linux-gnu-",
+            "aarch64",
+            &[],
+            &[],
+            &[],
+            Some("/opt/sysroot/usr/lib/aarch64-linux-gnu/pkgconfig"),
+        );
+        assert_eq!(
+            content,
+            "[binaries]\n\
+            c = ['aarch64-linux-gnu-gcc']\n\
+            cpp = ['aarch64-linux-gnu-g++']\n\
+            ar = 'aarch64-linux-gnu-ar'\n\
+            strip = 'aarch64-linux-gnu-strip'\n\
+            \n\
+            [host_machine]\n\
+            system = 'linux'\n\
+            cpu_family = 'aarch64'\n\
+            cpu = 'aarch64'\n\
+            endian = 'little'\n\
+            \n\
+            [properties]\n\
+            needs_exe_wrapper = true\n\
+            pkg_config_libdir = '/opt/sysroot/usr/lib/aarch64-linux-gnu/pkgconfig'\n",
+        );
+
+        let content = meson_cross_file_content(
+            "arm-linux-gnueabihf-",
+            "arm",
+            &["-mfpu=neon", "-mfloat-abi=hard"],
+            &["--sysroot=/opt/sysroot".to_string()],
+            &["-Wl,-rpath-link,/opt/sysroot/lib".to_string()],
+            Some("/opt/it's/pkgconfig"),
+        );
+        assert!(
+            content.contains("c = ['arm-linux-gnueabihf-gcc', '-mfpu=neon', '-mfloat-abi=hard']\n")
+        );
+        assert!(content.contains("cpu_family = 'arm'\ncpu = 'armv7'\n"));
+        assert!(content.contains("pkg_config_libdir = '/opt/it\\'s/pkgconfig'\n"));
+        assert!(content.ends_with(
+            "\n[built-in options]\n\
+            c_args = ['--sysroot=/opt/sysroot']\n\
+            c_link_args = ['-Wl,-rpath-link,/opt/sysroot/lib']\n"
+        ));
+        assert!(
+            !meson_cross_file_content("aarch64-linux-gnu-", "aarch64", &[], &[], &[], None)
+                .contains("pkg_config_libdir")
+        );
+    }
+}
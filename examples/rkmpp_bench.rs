@@ -1,11 +1,24 @@
-use clap::{Parser, ValueEnum};
+use clap::{CommandFactory, Parser, ValueEnum};
+use clap::error::ErrorKind;
 
-use rsmpeg::avcodec::{AVCodec, AVCodecContext};
+use rsmpeg::avcodec::{AVCodec, AVCodecContext, AVPacket};
 use rsmpeg::avutil::{self, ra, AVFrame};
 use rsmpeg::error::RsmpegError;
-use rsmpeg::ffi::{AV_PIX_FMT_YUV420P, AV_PIX_FMT_UYVY422};
-use rsmpeg::ffi::AV_CODEC_FLAG_LOW_DELAY;
+use rsmpeg::ffi;
+use rsmpeg::ffi::{AV_PIX_FMT_DRM_PRIME, AV_PIX_FMT_NV12, AV_PIX_FMT_YUV420P, AV_PIX_FMT_UYVY422};
+use rusty_ffmpeg::codec_flags::CodecFlags;
+use rusty_ffmpeg::filter::{FilterGraph, PulledFrame};
+use rusty_ffmpeg::swscale::Scaler;
+use rusty_ffmpeg::{
+    codec, csp, frame, hwdevice, imgutils, mathematics, opt, packet, parseutils, pixdesc, version,
+};
 
+use std::ffi::CStr;
+use std::fmt::Display;
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::ptr;
 use std::time::{Duration, Instant};
 
 /// Rockchip MPP Benchmark
@@ -13,23 +26,233 @@ use std::time::{Duration, Instant};
 #[command(version, about)]
 struct Args {
     /// Benchmark frame width
-    #[arg(long, short = 'w')]
-    width: u16,
+    #[arg(long, short = 'w', requires = "height")]
+    width: Option<u16>,
     /// Benchmark frame height
-    #[arg(long, short = 'h')]
-    height: u16,
-    /// Video codec
-    #[arg(long, short = 'c')]
-    codec: Codec,
-    /// Pixel format
-    #[arg(long, value_enum, default_value_t = PixelFormat::Yuv420p)]
-    pixel_format: PixelFormat,
+    #[arg(long, short = 'h', requires = "width")]
+    height: Option<u16>,
+    /// Benchmark frame size, either WxH or an abbreviation like hd1080 or vga
+    #[arg(long, value_parser = parse_size, conflicts_with_all = ["width", "height"])]
+    size: Option<(i32, i32)>,
+    /// Video codec, or `scale` to benchmark the RGA scaler
+    #[arg(long, short = 'c', required_unless_present_any = ["info", "list_options"])]
+    codec: Option<Codec>,
+    /// Output frame size of the scaler, the input frame size by default
+    #[arg(long, value_parser = parse_size)]
+    output_size: Option<(i32, i32)>,
+    /// Output pixel format name of the scaler
+    #[arg(long, value_parser = parse_pixel_format, default_value = "nv12")]
+    output_pixel_format: ffi::AVPixelFormat,
+    /// Pixel format name, e.g. yuv420p, nv12, nv16, uyvy422 or gray. Test
+    /// frames can be generated for the formats with 8-bit components
+    #[arg(long, value_parser = parse_pixel_format, default_value = "yuv420p")]
+    pixel_format: ffi::AVPixelFormat,
+    /// Read raw frames of the given size and pixel format from a file (`-` for
+    /// stdin) instead of generating them. The frames are looped if there are
+    /// fewer of them than --num-frames
+    #[arg(long, short = 'i')]
+    input: Option<PathBuf>,
+    /// Alignment in bytes of the linesizes of the planes in the --input file,
+    /// for files written with padded strides. Must be a power of two
+    #[arg(long, default_value_t = 1, requires = "input", value_parser = parse_align)]
+    input_align: i32,
+    /// Test pattern of the generated frames
+    #[arg(long, value_enum, default_value_t = Pattern::Gradient, conflicts_with = "input")]
+    pattern: Pattern,
+    /// Seed of the random test patterns. The encoded size depends on the
+    /// content of the frames, so compare sizes of runs with the same pattern
+    /// and seed. The gradient doesn't depend on it
+    #[arg(long, default_value_t = 0)]
+    seed: u64,
     /// Number of frames to process
     #[arg(long, default_value_t = 1000)]
-    num_frames: u32
+    num_frames: u32,
+    /// Keep encoding frames for the given number of seconds instead of a
+    /// fixed number of frames, e.g. to catch thermal throttling (encoders only)
+    #[arg(long, conflicts_with = "num_frames")]
+    duration: Option<u64>,
+    /// Number of codec threads, 0 lets FFmpeg pick it. The rkmpp codecs do
+    /// the work in the hardware, so it mostly matters for software codecs
+    #[arg(long)]
+    num_threads: Option<u32>,
+    /// Download decoded DRM-PRIME frames into NV12 (decoders), or the scaled
+    /// frames into memory (scaler)
+    #[arg(long)]
+    download: bool,
+    /// Print the size of every encoded packet
+    #[arg(long)]
+    per_frame_sizes: bool,
+    /// Report the statistics exported by the encoder with the packets: the
+    /// mean QP and the number of I-frames (encoders only). FFmpeg has no side
+    /// data for the processing time of the hardware, so the reported frame
+    /// times are always wall-clock ones
+    #[arg(long)]
+    hw_stats: bool,
+    /// Run the whole benchmark several times and report statistics over the runs
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    repeat: u32,
+    /// Reuse the codec context between the runs instead of opening a new one
+    #[arg(long)]
+    reuse_context: bool,
+    /// Open the codec and process a single frame to check that the codec
+    /// accepts the pixel format and the frame size, then exit. Exits with an
+    /// error if any step fails
+    #[arg(long)]
+    dry_run: bool,
+    /// Encode the frames, decode them back with the matching decoder and
+    /// compare them with the original ones, for either codec of the pair.
+    /// Reports the PSNR of every plane over all the frames and exits with an
+    /// error if any is below --min-psnr. Only lossless coding gives an
+    /// infinite PSNR, the rkmpp codecs are lossy so expect finite values
+    #[arg(long, conflicts_with_all = ["dry_run", "duration"])]
+    verify: bool,
+    /// Minimum PSNR in dB of every plane with --verify
+    #[arg(long, default_value_t = 30.0, requires = "verify")]
+    min_psnr: f64,
+    /// List the private options of a codec, or of all the rkmpp codecs when
+    /// no codec is given, with their types, defaults and ranges, then exit
+    #[arg(long, value_name = "CODEC")]
+    list_options: Option<Option<Codec>>,
+    /// Print the linesizes of the planes of the allocated frame
+    #[arg(long)]
+    dump_linesize: bool,
+    /// Print FFmpeg, rkmpp codecs and MPP driver information for bug reports and exit
+    #[arg(long)]
+    info: bool,
 }
 
-#[derive(Clone, Debug, ValueEnum)]
+impl Args {
+    fn codec(&self) -> &Codec {
+        self.codec.as_ref().expect("codec is required")
+    }
+
+    /// Frame size given either with `--size` or with `--width` and `--height`
+    fn frame_size(&self) -> (i32, i32) {
+        match (self.size, self.width, self.height) {
+            (Some(size), _, _) => size,
+            (None, Some(width), Some(height)) => (width as i32, height as i32),
+            _ => Args::command()
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    "frame size is required, use either --size or --width and --height",
+                )
+                .exit(),
+        }
+    }
+}
+
+/// Raw frames loaded from the input file
+struct RawInput {
+    data: Vec<u8>,
+    frame_size: usize,
+    align: i32,
+}
+
+impl RawInput {
+    fn read(args: &Args, path: &PathBuf) -> RawInput {
+        let (width, height) = args.frame_size();
+        let align = args.input_align;
+        let frame_size = imgutils::buffer_size(args.pixel_format, width, height, align).unwrap_or_else(|e| {
+            Args::command()
+                .error(ErrorKind::ValueValidation, format!("invalid frame size: {e}"))
+                .exit()
+        });
+
+        let data = if path.as_os_str() == "-" {
+            let mut data = vec!();
+            io::stdin().lock().read_to_end(&mut data).map(|_| data)
+        } else {
+            fs::read(path)
+        };
+        let data = data.unwrap_or_else(|e| {
+            Args::command()
+                .error(ErrorKind::Io, format!("cannot read {}: {e}", path.display()))
+                .exit()
+        });
+        let truncated_size = data.len() % frame_size;
+        if data.is_empty() || truncated_size != 0 {
+            Args::command()
+                .error(
+                    ErrorKind::ValueValidation,
+                    format!(
+                        "input size {} is not a multiple of the frame size {frame_size} of {width}x{height} {} \
+                        frames with {align}-byte aligned linesizes, the last frame is truncated to {truncated_size} bytes",
+                        data.len(),
+                        pixdesc::pix_fmt_name(args.pixel_format).unwrap_or_default(),
+                    ),
+                )
+                .exit()
+        }
+        let input = RawInput { data, frame_size, align };
+        println!(
+            "Input: {} frames of {frame_size} bytes, linesizes {:?}",
+            input.num_frames(),
+            input.linesizes(args.pixel_format, width),
+        );
+        input
+    }
+
+    /// Linesizes of the used planes of the input frames
+    fn linesizes(&self, pix_fmt: ffi::AVPixelFormat, width: i32) -> Vec<i32> {
+        let mut linesizes = [0; 4];
+        let ret = unsafe { ffi::av_image_fill_linesizes(linesizes.as_mut_ptr(), pix_fmt, width) };
+        assert!(ret >= 0, "fill image linesizes: {}", ffi::av_err2str(ret));
+        let num_planes = pixdesc::count_planes(pix_fmt).expect("known pixel format");
+        linesizes[..num_planes]
+            .iter()
+            .map(|&linesize| (linesize + self.align - 1) & !(self.align - 1))
+            .collect()
+    }
+
+    fn num_frames(&self) -> usize {
+        self.data.len() / self.frame_size
+    }
+
+    /// Copies the `i`-th input frame into the frame planes
+    fn fill_frame(&self, frame: &mut ffi::AVFrame, i: usize) {
+        let offset = (i % self.num_frames()) * self.frame_size;
+        let mut src_data = [ptr::null_mut(); 4];
+        let mut src_linesize = [0; 4];
+        unsafe {
+            let ret = ffi::av_image_fill_arrays(
+                src_data.as_mut_ptr(),
+                src_linesize.as_mut_ptr(),
+                self.data[offset..].as_ptr(),
+                frame.format,
+                frame.width,
+                frame.height,
+                self.align,
+            );
+            assert!(ret >= 0, "fill image arrays: {}", ffi::av_err2str(ret));
+            ffi::av_image_copy(
+                frame.data.as_ptr(),
+                frame.linesize.as_ptr(),
+                src_data.as_ptr().cast(),
+                src_linesize.as_ptr(),
+                frame.format,
+                frame.width,
+                frame.height,
+            );
+        }
+    }
+}
+
+fn parse_pixel_format(s: &str) -> Result<ffi::AVPixelFormat, String> {
+    pixdesc::pix_fmt_from_name(s).ok_or_else(|| format!("unknown pixel format: {s}"))
+}
+
+fn parse_align(s: &str) -> Result<i32, String> {
+    match s.parse::<i32>() {
+        Ok(align) if align > 0 && (align as u32).is_power_of_two() => Ok(align),
+        _ => Err(format!("alignment must be a power of two: {s}")),
+    }
+}
+
+fn parse_size(s: &str) -> Result<(i32, i32), String> {
+    parseutils::parse_video_size(s).ok_or_else(|| format!("invalid frame size: {s}"))
+}
+
+#[derive(Clone, Copy, Debug, ValueEnum)]
 enum Codec {
     #[value(alias("mjpeg_enc"))]
     MjpegEnc,
@@ -37,128 +260,1094 @@ enum Codec {
     H264Enc,
     #[value(alias("hevc_enc"))]
     HevcEnc,
-    // TODO
-    // #[value(alias("mjpeg_dec"))]
-    // MjpegDec,
-    // #[value(alias("h264_dec"))]
-    // H264Dec,
-    // #[value(alias("hevc_dec"))]
-    // HevcDec,
+    #[value(alias("mjpeg_dec"))]
+    MjpegDec,
+    #[value(alias("h264_dec"))]
+    H264Dec,
+    #[value(alias("hevc_dec"))]
+    HevcDec,
+    #[value(alias("scale_rkrga"))]
+    Scale,
 }
 
-#[derive(Clone, Debug, ValueEnum)]
-enum PixelFormat {
-    #[value(alias("uyvy422"))]
-    Uyvy422,
-    #[value(alias("yuv420p"))]
-    Yuv420p,
+#[derive(Clone, Copy, Debug, ValueEnum)]
+enum Pattern {
+    /// Moving gradient, which compresses well
+    Gradient,
+    /// Pseudo-random noise, the worst case for the encoders
+    Noise,
+}
+
+impl Codec {
+    /// Name of the rkmpp codec, or of the RGA filter for the scaler
+    fn rkmpp_name(&self) -> &'static CStr {
+        match self {
+            Codec::MjpegEnc | Codec::MjpegDec => c"mjpeg_rkmpp",
+            Codec::H264Enc | Codec::H264Dec => c"h264_rkmpp",
+            Codec::HevcEnc | Codec::HevcDec => c"hevc_rkmpp",
+            Codec::Scale => c"scale_rkrga",
+        }
+    }
+
+    fn is_decoder(&self) -> bool {
+        matches!(self, Codec::MjpegDec | Codec::H264Dec | Codec::HevcDec)
+    }
+
+    fn is_scaler(&self) -> bool {
+        matches!(self, Codec::Scale)
+    }
 }
 
 fn main() {
     let args = Args::parse();
 
+    if args.info {
+        print_info();
+        return;
+    }
+    if args.verify && !test_pattern_supported(args.pixel_format) {
+        Args::command()
+            .error(
+                ErrorKind::InvalidValue,
+                "--verify only supports pixel formats with byte-sized components",
+            )
+            .exit();
+    }
+    if args.input.is_none() && args.list_options.is_none() && !test_pattern_supported(args.pixel_format) {
+        Args::command()
+            .error(
+                ErrorKind::InvalidValue,
+                format!(
+                    "cannot generate test frames in {} pixel format, use --input to provide them",
+                    pixdesc::pix_fmt_name(args.pixel_format).unwrap_or_default()
+                ),
+            )
+            .exit();
+    }
+
     println!("FFMpeg version: {}", avutil::version_info().to_string_lossy());
 
     println!("Available codecs:");
     for av_codec_ref in AVCodec::iterate() {
-        println!("- {}, {}, {}", av_codec_ref.name().to_string_lossy(), av_codec_ref.long_name().to_string_lossy(), av_codec_ref.id);
+        let kind = if codec::is_hardware(&av_codec_ref) { "HW" } else { "SW" };
+        println!("- [{kind}] {}, {}, {}", av_codec_ref.name().to_string_lossy(), av_codec_ref.long_name().to_string_lossy(), av_codec_ref.id);
     }
 
-    let width = args.width as usize;
-    let height = args.height as usize;
-
-    let codec = match args.codec {
-        Codec::MjpegEnc => {
-            AVCodec::find_encoder_by_name(c"mjpeg_rkmpp")
+    if let Some(list_codec) = args.list_options {
+        match list_codec.or(args.codec) {
+            Some(codec) => list_options(codec),
+            None => Codec::value_variants()
+                .iter()
+                .copied()
+                .filter(|codec| !codec.is_scaler())
+                .for_each(list_options),
         }
-        Codec::H264Enc => {
-            AVCodec::find_encoder_by_name(c"h264_rkmpp")
-        }
-        Codec::HevcEnc => {
-            AVCodec::find_encoder_by_name(c"hevc_rkmpp")
+    } else if args.dry_run {
+        dry_run(&args);
+    } else if args.verify {
+        verify(&args);
+    } else if args.codec().is_scaler() {
+        scale(&args);
+    } else if args.codec().is_decoder() {
+        decode(&args);
+    } else {
+        encode(&args);
+    }
+}
+
+/// Prints a diagnostic block to attach to performance reports
+fn print_info() {
+    println!("FFmpeg version: {}", avutil::version_info().to_string_lossy());
+    println!("FFmpeg configuration: {}", version::configuration());
+    println!("FFmpeg license: {}", version::license());
+    println!("Kernel: {}", read_info_file("/proc/version"));
+    println!("Device: {}", read_info_file("/proc/device-tree/compatible"));
+    println!(
+        "MPP service: {}",
+        if Path::new("/dev/mpp_service").exists() { "/dev/mpp_service" } else { "not found" }
+    );
+    println!("MPP service version: {}", read_info_file("/proc/mpp_service/version"));
+
+    println!("rkmpp codecs:");
+    for av_codec_ref in AVCodec::iterate() {
+        let name = av_codec_ref.name().to_string_lossy();
+        if !name.ends_with("_rkmpp") {
+            continue;
         }
-        // Codec::MjpegDec => {
-        //     AVCodec::find_decoder_by_name(c"mjpeg_rkmpp")
-        // }
-        // Codec::H264Dec => {
-        //     AVCodec::find_decoder_by_name(c"h264_rkmpp")
-        // }
-        // Codec::HevcDec => {
-        //     AVCodec::find_decoder_by_name(c"hevc_rkmpp")
-        // }
+        let kind = if unsafe { ffi::av_codec_is_decoder(av_codec_ref.as_ptr()) } != 0 {
+            "decoder"
+        } else {
+            "encoder"
+        };
+        let caps = codec::capabilities(&av_codec_ref);
+        let cap_names = [
+            (ffi::AV_CODEC_CAP_HARDWARE, "hardware"),
+            (ffi::AV_CODEC_CAP_HYBRID, "hybrid"),
+            (ffi::AV_CODEC_CAP_DELAY, "delay"),
+            (ffi::AV_CODEC_CAP_AVOID_PROBING, "avoid_probing"),
+            (ffi::AV_CODEC_CAP_ENCODER_FLUSH, "encoder_flush"),
+        ]
+            .into_iter()
+            .filter(|(cap, _)| caps & cap != 0)
+            .map(|(_, name)| name)
+            .collect::<Vec<_>>();
+        let pix_fmts = codec::supported_pix_fmts(&av_codec_ref)
+            .into_iter()
+            .map(|pix_fmt| unsafe { CStr::from_ptr(ffi::av_get_pix_fmt_name(pix_fmt)) }.to_string_lossy())
+            .collect::<Vec<_>>();
+        println!("- {name} ({kind}), capabilities: {caps:#x} [{}]", cap_names.join(", "));
+        println!("  pixel formats: {}", pix_fmts.join(", "));
+    }
+}
+
+/// Reads a diagnostic file, which may contain NUL-separated values
+fn read_info_file(path: &str) -> String {
+    match fs::read(path) {
+        Ok(content) => String::from_utf8_lossy(&content)
+            .split(['\0', '\n'])
+            .filter(|v| !v.is_empty())
+            .collect::<Vec<_>>()
+            .join(", "),
+        Err(e) => format!("unavailable ({e})"),
+    }
+}
+
+/// Prints the private options of an rkmpp codec. They are the options of its
+/// `priv_data`, so the generic `AVCodecContext` options are not listed
+fn list_options(codec: Codec) {
+    let name = codec.rkmpp_name().to_string_lossy();
+    if codec.is_scaler() {
+        println!("{name} is a filter, its options are listed by `ffmpeg -h filter={name}`");
+        return;
+    }
+    let kind = if codec.is_decoder() { "decoder" } else { "encoder" };
+    let av_codec = if codec.is_decoder() {
+        AVCodec::find_decoder_by_name(codec.rkmpp_name())
+    } else {
+        AVCodec::find_encoder_by_name(codec.rkmpp_name())
     };
-    let pixel_format = match args.pixel_format {
-        PixelFormat::Yuv420p => AV_PIX_FMT_YUV420P,
-        PixelFormat::Uyvy422 => AV_PIX_FMT_UYVY422,
+    let Some(av_codec) = av_codec else {
+        println!("{name} {kind} is not available");
+        return;
     };
+    let codec_ctx = AVCodecContext::new(&av_codec);
+    println!("{name} {kind} options:");
+    for option in unsafe { opt::list_options(codec_ctx.priv_data) } {
+        let help = option.help.as_deref().unwrap_or_default();
+        if option.type_ == ffi::AV_OPT_TYPE_CONST {
+            println!("     {:<16} {help}", option.name);
+            continue;
+        }
+        let default = option.default.as_deref().unwrap_or("none");
+        let is_numeric = matches!(
+            option.type_,
+            ffi::AV_OPT_TYPE_INT
+                | ffi::AV_OPT_TYPE_INT64
+                | ffi::AV_OPT_TYPE_UINT
+                | ffi::AV_OPT_TYPE_UINT64
+                | ffi::AV_OPT_TYPE_DOUBLE
+                | ffi::AV_OPT_TYPE_FLOAT
+        );
+        let range = if is_numeric {
+            format!(", from {} to {}", option.min, option.max)
+        } else {
+            String::new()
+        };
+        println!(
+            "  -{:<16} <{}> {help} (default {default}{range})",
+            option.name,
+            option.type_name()
+        );
+    }
+}
+
+fn open_encoder(args: &Args) -> AVCodecContext {
+    let mut codec_ctx = new_encoder(args);
+    open_codec(&mut codec_ctx);
+    codec_ctx
+}
+
+/// Encoder context configured with the benchmark parameters, not opened yet
+fn new_encoder(args: &Args) -> AVCodecContext {
+    let codec = AVCodec::find_encoder_by_name(args.codec().rkmpp_name());
     let mut codec_ctx = AVCodecContext::new(&codec.expect("codec not found"));
-    codec_ctx.set_pix_fmt(pixel_format);
-    codec_ctx.set_width(width as i32);
-    codec_ctx.set_height(height as i32);
-    codec_ctx.set_flags(AV_CODEC_FLAG_LOW_DELAY as i32);
+    let (width, height) = args.frame_size();
+    codec_ctx.set_pix_fmt(args.pixel_format);
+    codec_ctx.set_width(width);
+    codec_ctx.set_height(height);
+    CodecFlags::LOW_DELAY.set_on(unsafe { &mut *codec_ctx.as_mut_ptr() });
     codec_ctx.set_time_base(ra(1, 25));
+    set_thread_count(args, &mut codec_ctx);
+    codec_ctx
+}
 
-    codec_ctx.open(None).expect("codec context open");
+/// Opens the codec and passes a single frame through it, reporting every step
+fn dry_run(args: &Args) {
+    let input = args.input.as_ref().map(|path| RawInput::read(args, path));
+    let mut frame = alloc_frame(args);
+    generate_frame(args, input.as_ref(), &mut frame, 0);
 
+    if args.codec().is_scaler() {
+        let mut graph = report_step("Create filter graph", new_scale_graph(args));
+        report_step("Push frame", graph.push_frame(Some(&frame)));
+        report_step("Flush filter graph", graph.push_frame(None));
+        let frame = report_step("Pull frame", graph.pull_frame());
+        match frame {
+            PulledFrame::Frame(frame) => println!(
+                "Frame: {}x{}, pixel format {}",
+                frame.width,
+                frame.height,
+                pixdesc::pix_fmt_name(frame.format).unwrap_or_default()
+            ),
+            PulledFrame::Again | PulledFrame::Eof => println!("No frames"),
+        }
+        return;
+    }
+
+    if !args.codec().is_decoder() {
+        let mut codec_ctx = new_encoder(args);
+        report_step("Open encoder", codec_ctx.open(None));
+        print_codec_params(&codec_ctx);
+        report_step("Send frame", codec_ctx.send_frame(Some(&frame)));
+        report_step("Flush encoder", codec_ctx.send_frame(None));
+        let packets = report_step("Receive packets", receive_all_packets(&mut codec_ctx));
+        let total_size = packets.iter().map(|packet| packet.size as usize).sum::<usize>();
+        println!("{} packets, {total_size} bytes", packets.len());
+        return;
+    }
+
+    // Prepare the input with the matching encoder
+    let mut encoder_ctx = open_encoder(args);
+    encoder_ctx.send_frame(Some(&frame)).expect("send frame");
+    encoder_ctx.send_frame(None).expect("send frame");
+    let packets = receive_all_packets(&mut encoder_ctx).expect("receive packets");
+
+    let codec = AVCodec::find_decoder_by_name(args.codec().rkmpp_name()).expect("codec not found");
+    let mut codec_ctx = AVCodecContext::new(&codec);
+    let _get_format = unsafe { codec::set_get_format_drm_prime(codec_ctx.as_mut_ptr()) };
+    set_thread_count(args, &mut codec_ctx);
+    report_step("Open decoder", codec_ctx.open(None));
+    print_codec_params(&codec_ctx);
+    for packet in &packets {
+        report_step("Send packet", codec_ctx.send_packet(Some(packet)));
+    }
+    report_step("Flush decoder", codec_ctx.send_packet(None));
+    let frames = report_step("Receive frames", receive_all_frames(&mut codec_ctx));
+    println!("{} frames", frames.len());
+    if let Some(frame) = frames.first() {
+        println!(
+            "Frame: {}x{}, pixel format {}",
+            frame.width,
+            frame.height,
+            pixdesc::pix_fmt_name(frame.format).unwrap_or_default()
+        );
+        print_color_properties(frame);
+    }
+}
+
+/// Prints the outcome of a dry run step, exits on failure
+fn report_step<T, E: Display>(step: &str, result: Result<T, E>) -> T {
+    match result {
+        Ok(value) => {
+            println!("{step}: ok");
+            value
+        }
+        Err(e) => {
+            println!("{step}: failed, {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn print_codec_params(codec_ctx: &AVCodecContext) {
+    println!(
+        "Codec: {}x{}, pixel format {}, time base {}/{}, threads {}",
+        codec_ctx.width,
+        codec_ctx.height,
+        pixdesc::pix_fmt_name(codec_ctx.pix_fmt).unwrap_or_default(),
+        codec_ctx.time_base.num,
+        codec_ctx.time_base.den,
+        codec_ctx.thread_count,
+    );
+}
+
+/// Receives the packets of a flushed encoder
+fn receive_all_packets(codec_ctx: &mut AVCodecContext) -> Result<Vec<AVPacket>, RsmpegError> {
+    let mut packets = vec!();
+    loop {
+        match codec_ctx.receive_packet() {
+            Ok(packet) => packets.push(packet),
+            Err(RsmpegError::EncoderDrainError) | Err(RsmpegError::EncoderFlushedError) => return Ok(packets),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Receives the frames of a flushed decoder
+fn receive_all_frames(codec_ctx: &mut AVCodecContext) -> Result<Vec<AVFrame>, RsmpegError> {
+    let mut frames = vec!();
+    loop {
+        match codec_ctx.receive_frame() {
+            Ok(frame) => frames.push(frame),
+            Err(RsmpegError::DecoderDrainError) | Err(RsmpegError::DecoderFlushedError) => return Ok(frames),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Opens the codec, waiting for the MPP device if it is busy
+fn open_codec(codec_ctx: &mut AVCodecContext) {
+    let ret = unsafe {
+        codec::open_with_retry(codec_ctx.as_mut_ptr(), ptr::null(), ptr::null_mut(), 5, Duration::from_millis(100))
+    };
+    if let Err(e) = ret {
+        panic!("codec context open: {e}");
+    }
+}
+
+/// Warns when the input pixel format is not the one preferred by the encoder,
+/// as the implicit conversion can dominate the benchmark numbers
+fn warn_pix_fmt_conversion(codec_ctx: &AVCodecContext) {
+    let codec = codec_ctx.codec();
+    // The formats are listed in the order of preference, skip DRM-PRIME as
+    // generated frames are always in memory
+    let Some(preferred) = codec::supported_pix_fmts(&codec)
+        .into_iter()
+        .find(|&pix_fmt| !pixdesc::is_hwaccel(pix_fmt))
+    else {
+        return;
+    };
+    if codec_ctx.pix_fmt != preferred {
+        let name = |pix_fmt| pixdesc::pix_fmt_name(pix_fmt).unwrap_or_default();
+        println!(
+            "Warning: {} prefers {} input, {} frames are likely converted before encoding, \
+            which can slow the benchmark down",
+            codec.name().to_string_lossy(),
+            name(preferred),
+            name(codec_ctx.pix_fmt),
+        );
+    }
+}
+
+fn set_thread_count(args: &Args, codec_ctx: &mut AVCodecContext) {
+    if let Some(num_threads) = args.num_threads {
+        unsafe { (*codec_ctx.as_mut_ptr()).thread_count = num_threads as i32 };
+    }
+}
+
+fn alloc_frame(args: &Args) -> AVFrame {
+    let (width, height) = args.frame_size();
     let mut frame = AVFrame::new();
-    frame.set_format(pixel_format);
-    frame.set_width(width as i32);
-    frame.set_height(height as i32);
+    frame.set_format(args.pixel_format);
+    frame.set_width(width);
+    frame.set_height(height);
     frame.alloc_buffer().expect("alloc frame buffer");
 
-    // let linesize_count = frame.data.iter().map(|plane| !plane.is_null()).count();
-    // println!("Linesize count: {linesize_count}");
+    if args.dump_linesize {
+        let num_planes = used_planes(&frame);
+        println!("Linesize count: {num_planes}");
+        for (plane, linesize) in frame.linesize[..num_planes].iter().enumerate() {
+            println!("Plane #{plane} linesize: {linesize}");
+        }
+    }
+
+    frame
+}
 
+/// Number of planes used by the frame pixel format, the remaining entries of
+/// the fixed-size `data` and `linesize` arrays are unused
+fn used_planes(frame: &ffi::AVFrame) -> usize {
+    pixdesc::count_planes(frame.format).expect("known pixel format")
+}
+
+fn generate_frame(args: &Args, input: Option<&RawInput>, frame: &mut AVFrame, i: usize) {
+    frame.make_writable().expect("make frame writable");
+    let raw_frame = unsafe { &mut *frame.as_mut_ptr() };
+    match input {
+        Some(input) => input.fill_frame(raw_frame, i),
+        None => fill_test_pattern(raw_frame, i, args.pattern, args.seed),
+    };
+    frame.set_pts(i as i64);
+}
+
+/// Receives all the packets available at the moment
+fn receive_packets(codec_ctx: &mut AVCodecContext, mut on_packet: impl FnMut(AVPacket)) {
+    loop {
+        let packet = match codec_ctx.receive_packet() {
+            Ok(packet) => packet,
+            Err(RsmpegError::EncoderDrainError) | Err(RsmpegError::EncoderFlushedError) => break,
+            Err(e) => panic!("{e}"),
+        };
+        on_packet(packet);
+    }
+}
+
+/// Maximum number of free frames kept by the encoder frame pool
+const FRAME_POOL_CAPACITY: usize = 4;
+
+/// Recycles frames between the generation and the encoding, so their buffers
+/// are not reallocated for every frame
+struct FramePool<F> {
+    alloc: F,
+    free: Vec<AVFrame>,
+    capacity: usize,
+}
+
+impl<F: FnMut() -> AVFrame> FramePool<F> {
+    fn new(capacity: usize, alloc: F) -> Self {
+        FramePool { alloc, free: vec!(), capacity }
+    }
+
+    /// Hands out a writable frame, allocating one if there are no free frames
+    fn get(&mut self) -> AVFrame {
+        // The encoder may still reference the buffers of a frame, prefer the
+        // frames released by it as the other ones have to be copied
+        let writable_ix = self.free.iter()
+            .position(|frame| unsafe { ffi::av_frame_is_writable(frame.as_ptr().cast_mut()) } != 0);
+        let mut frame = match writable_ix {
+            Some(ix) => self.free.swap_remove(ix),
+            None => self.free.pop().unwrap_or_else(&mut self.alloc),
+        };
+        frame.make_writable().expect("make frame writable");
+        frame
+    }
+
+    /// Returns a frame to the pool, it is dropped if the pool is full
+    fn put(&mut self, frame: AVFrame) {
+        if self.free.len() < self.capacity {
+            self.free.push(frame);
+        }
+    }
+}
+
+/// Statistics exported by the encoder with the packets, see `--hw-stats`
+#[derive(Debug, Default)]
+struct HwStats {
+    num_packets: u32,
+    /// Number of the packets with statistics attached
+    num_reported: u32,
+    quality_sum: u64,
+    num_intra: u32,
+}
+
+impl HwStats {
+    fn add(&mut self, packet: &ffi::AVPacket) {
+        self.num_packets += 1;
+        if let Some(stats) = packet::quality_stats(packet) {
+            self.num_reported += 1;
+            self.quality_sum += stats.quality as u64;
+            if stats.pict_type == ffi::AV_PICTURE_TYPE_I {
+                self.num_intra += 1;
+            }
+        }
+    }
+
+    fn merge(&mut self, other: &HwStats) {
+        self.num_packets += other.num_packets;
+        self.num_reported += other.num_reported;
+        self.quality_sum += other.quality_sum;
+        self.num_intra += other.num_intra;
+    }
+
+    fn print(&self) {
+        if self.num_reported == 0 {
+            println!("Encoder stats: not exported by the encoder");
+            return;
+        }
+        let mean_qp = self.quality_sum as f64 / self.num_reported as f64 / ffi::FF_QP2LAMBDA as f64;
+        println!(
+            "Encoder stats: {} of {} packets, mean QP {mean_qp:.1}, {} I-frames",
+            self.num_reported, self.num_packets, self.num_intra
+        );
+    }
+}
+
+/// Results of a single encoding run
+struct EncodeRun {
+    num_frames: u32,
+    total_time: Duration,
+    encode_time: Duration,
+    total_size: usize,
+    /// Sizes of the packets and whether they are keyframes, only collected
+    /// with `--per-frame-sizes`
+    packet_sizes: Vec<(usize, bool)>,
+    /// Only collected with `--hw-stats`
+    hw_stats: HwStats,
+}
+
+impl EncodeRun {
+    fn fps(&self) -> f64 {
+        self.num_frames as f64 / self.encode_time.as_secs_f64()
+    }
+
+    fn frame_time(&self) -> Duration {
+        self.encode_time / self.num_frames.max(1)
+    }
+
+    /// Bitrate of the encoded stream in bits per second at the nominal frame rate
+    fn bitrate(&self, frame_rate: f64) -> f64 {
+        self.total_size as f64 * 8.0 * frame_rate / self.num_frames as f64
+    }
+}
+
+fn encode(args: &Args) {
+    let input = args.input.as_ref().map(|path| RawInput::read(args, path));
+    let mut codec_ctx = open_encoder(args);
+    println!("Encoder threads: {}", codec_ctx.thread_count);
+    warn_pix_fmt_conversion(&codec_ctx);
+    let mut frame_pool = FramePool::new(FRAME_POOL_CAPACITY, || alloc_frame(args));
+    if args.reuse_context
+        && codec_ctx.codec().capabilities & ffi::AV_CODEC_CAP_ENCODER_FLUSH as i32 == 0
+    {
+        panic!("{} encoder doesn't support flushing, cannot reuse its context", codec_ctx.codec().name().to_string_lossy());
+    }
+
+    // Frames are timestamped with the time base, so it gives the nominal frame rate
+    let frame_rate = codec_ctx.time_base.den as f64 / codec_ctx.time_base.num as f64;
+
+    let mut runs = vec!();
+    for run_ix in 0..args.repeat {
+        if run_ix > 0 {
+            if args.reuse_context {
+                unsafe { ffi::avcodec_flush_buffers(codec_ctx.as_mut_ptr()) };
+            } else {
+                codec_ctx = open_encoder(args);
+            }
+        }
+        let run = encode_run(args, input.as_ref(), &mut codec_ctx, &mut frame_pool);
+        for (packet_ix, (size, is_keyframe)) in run.packet_sizes.iter().enumerate() {
+            println!("Packet #{packet_ix}: {size}{}", if *is_keyframe { " (key)" } else { "" });
+        }
+        if args.repeat > 1 {
+            println!(
+                "Run #{}: {:.2} fps, 1 frame for {:?}, {:.0} kbit/s",
+                run_ix + 1,
+                run.fps(),
+                run.frame_time(),
+                run.bitrate(frame_rate) / 1000.0
+            );
+        }
+        runs.push(run);
+    }
+
+    if args.repeat > 1 {
+        let fps = runs.iter().map(EncodeRun::fps).collect::<Vec<_>>();
+        let frame_times = runs.iter()
+            .map(|run| run.frame_time().as_secs_f64())
+            .collect::<Vec<_>>();
+        let (fps_mean, fps_std_dev) = mean_and_std_dev(&fps);
+        let (frame_time_mean, frame_time_std_dev) = mean_and_std_dev(&frame_times);
+        let (best_run_ix, best_run) = runs.iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.fps().total_cmp(&b.fps()))
+            .expect("at least one run");
+        if let Some(duration) = args.duration {
+            println!("{} runs of {duration}s", args.repeat);
+        } else {
+            println!("{} runs of {} frames", args.repeat, args.num_frames);
+        }
+        println!("FPS: {fps_mean:.2} ± {fps_std_dev:.2}");
+        println!(
+            "1 frame for {:?} ± {:?}",
+            Duration::from_secs_f64(frame_time_mean), Duration::from_secs_f64(frame_time_std_dev)
+        );
+        println!("Best run #{}: {:.2} fps", best_run_ix + 1, best_run.fps());
+    } else {
+        let run = &runs[0];
+        println!("{} frames processed for {:?}", run.num_frames, run.total_time);
+        println!("{} frames encoded for {:?}", run.num_frames, run.encode_time);
+        println!("1 frame for {:?}", run.frame_time());
+        if args.duration.is_some() {
+            println!("Average FPS: {:.2}", run.fps());
+        }
+        println!("Total encoded size: {}", run.total_size);
+        println!("Bitrate at {frame_rate} fps: {:.0} kbit/s", run.bitrate(frame_rate) / 1000.0);
+    }
+    if args.hw_stats {
+        let mut hw_stats = HwStats::default();
+        for run in runs.iter() {
+            hw_stats.merge(&run.hw_stats);
+        }
+        hw_stats.print();
+    }
+}
+
+fn encode_run(
+    args: &Args,
+    input: Option<&RawInput>,
+    codec_ctx: &mut AVCodecContext,
+    frame_pool: &mut FramePool<impl FnMut() -> AVFrame>,
+) -> EncodeRun {
     let start_at = Instant::now();
     let mut gen_frame_total_time = Duration::ZERO;
 
     let mut total_size = 0;
-    for i in 0..args.num_frames as usize {
-        frame.make_writable().expect("make frame writable");
-
-        let gen_frame_start_at = Instant::now();
-        match args.pixel_format {
-            PixelFormat::Yuv420p => generate_yuv420p_frame(&mut frame, i),
-            PixelFormat::Uyvy422 => generate_uyvy422_frame(&mut frame, i),
+    let mut packet_sizes = vec!();
+    let mut hw_stats = HwStats::default();
+    let mut on_packet = |packet: AVPacket| {
+        total_size += packet.size as usize;
+        if args.per_frame_sizes {
+            packet_sizes.push((packet.size as usize, packet::is_keyframe(&packet)));
+        }
+        if args.hw_stats {
+            hw_stats.add(&packet);
+        }
+    };
+    let duration = args.duration.map(Duration::from_secs);
+    let mut num_frames = 0;
+    loop {
+        let done = match duration {
+            Some(duration) => start_at.elapsed() >= duration,
+            None => num_frames >= args.num_frames,
         };
+        if done {
+            break;
+        }
+        let gen_frame_start_at = Instant::now();
+        let mut frame = frame_pool.get();
+        // The frame index is also the pts, so it keeps increasing
+        generate_frame(args, input, &mut frame, num_frames as usize);
+        num_frames += 1;
         gen_frame_total_time += gen_frame_start_at.elapsed();
 
-        frame.set_pts(i as i64);
-
         codec_ctx.send_frame(Some(&frame)).expect("send frame");
-        loop {
-            let packet = match codec_ctx.receive_packet() {
-                Ok(packet) => packet,
-                Err(RsmpegError::EncoderDrainError) | Err(RsmpegError::EncoderFlushedError) => break,
-                Err(e) => panic!("{e}"),
+        frame_pool.put(frame);
+        receive_packets(codec_ctx, &mut on_packet);
+    }
+    codec_ctx.send_frame(None).expect("send frame");
+    receive_packets(codec_ctx, &mut on_packet);
+
+    let total_time = start_at.elapsed();
+    EncodeRun {
+        num_frames,
+        total_time,
+        encode_time: total_time - gen_frame_total_time,
+        total_size,
+        packet_sizes,
+        hw_stats,
+    }
+}
+
+fn mean_and_std_dev(values: &[f64]) -> (f64, f64) {
+    let mean = values.iter().sum::<f64>() / values.len() as f64;
+    let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / values.len() as f64;
+    (mean, variance.sqrt())
+}
+
+/// Span of the timestamps of the decoded frames, to compare the decoding time
+/// with the duration of the video
+#[derive(Debug, Default)]
+struct DecodedSpan {
+    /// The smallest timestamp and the largest end of a frame
+    range: Option<(i64, i64)>,
+    num_without_ts: u32,
+}
+
+impl DecodedSpan {
+    fn add(&mut self, frame: &ffi::AVFrame) {
+        let Some(ts) = frame::best_effort_timestamp(frame) else {
+            self.num_without_ts += 1;
+            return;
+        };
+        let end = ts + frame::duration(frame).unwrap_or(0);
+        self.range = Some(match self.range {
+            Some((start, prev_end)) => (start.min(ts), prev_end.max(end)),
+            None => (ts, end),
+        });
+    }
+
+    /// Duration of the decoded video, `None` if no frame has a timestamp
+    fn duration(&self, time_base: ffi::AVRational) -> Option<Duration> {
+        let (start, end) = self.range?;
+        let micros = mathematics::rescale_q(end - start, time_base, ra(1, 1_000_000));
+        Some(Duration::from_micros(micros.max(0) as u64))
+    }
+}
+
+/// Encodes the frames with the encoder matching the codec, returns the
+/// packets and their time base
+fn encode_frames(args: &Args, input: Option<&RawInput>) -> (Vec<AVPacket>, ffi::AVRational) {
+    let mut encoder_ctx = open_encoder(args);
+    let mut frame = alloc_frame(args);
+    let mut packets = vec!();
+    for i in 0..args.num_frames as usize {
+        generate_frame(args, input, &mut frame, i);
+        encoder_ctx.send_frame(Some(&frame)).expect("send frame");
+        receive_packets(&mut encoder_ctx, |packet| packets.push(packet));
+    }
+    encoder_ctx.send_frame(None).expect("send frame");
+    receive_packets(&mut encoder_ctx, |packet| packets.push(packet));
+    (packets, encoder_ctx.time_base)
+}
+
+fn decode(args: &Args) {
+    // Prepare the input by encoding generated frames with the matching encoder
+    let input = args.input.as_ref().map(|path| RawInput::read(args, path));
+    let (packets, time_base) = encode_frames(args, input.as_ref());
+
+    let codec = AVCodec::find_decoder_by_name(args.codec().rkmpp_name()).expect("codec not found");
+    if !codec::supported_pix_fmts(&codec).contains(&AV_PIX_FMT_DRM_PRIME) {
+        println!("Decoder doesn't support DRM-PRIME output, frames are decoded into memory");
+    }
+    let mut codec_ctx = AVCodecContext::new(&codec);
+    // The timestamps of the packets are in the time base of the encoder
+    unsafe { (*codec_ctx.as_mut_ptr()).pkt_timebase = time_base };
+    // Keep decoded frames in DRM buffers
+    let _get_format = unsafe { codec::set_get_format_drm_prime(codec_ctx.as_mut_ptr()) };
+    set_thread_count(args, &mut codec_ctx);
+    open_codec(&mut codec_ctx);
+    println!("Decoder threads: {}", codec_ctx.thread_count);
+
+    let mut sw_frame = AVFrame::new();
+    sw_frame.set_format(AV_PIX_FMT_NV12);
+
+    let start_at = Instant::now();
+    let mut download_total_time = Duration::ZERO;
+    let mut num_decoded_frames = 0u32;
+    let mut decoded_span = DecodedSpan::default();
+    let mut on_frame = |mut frame: AVFrame| {
+        num_decoded_frames += 1;
+        decoded_span.add(&frame);
+        // The decoder outputs frames of the aligned size, crop them to the
+        // displayable region like a real consumer would
+        let raw_frame = unsafe { &mut *frame.as_mut_ptr() };
+        let coded_size = (raw_frame.width, raw_frame.height);
+        frame::apply_cropping(raw_frame, 0).expect("crop frame");
+        if num_decoded_frames == 1 {
+            if coded_size != (frame.width, frame.height) {
+                println!(
+                    "Frames are cropped from {}x{} to {}x{}",
+                    coded_size.0, coded_size.1, frame.width, frame.height
+                );
+            }
+            print_color_properties(&frame);
+        }
+        if args.download && frame.format == AV_PIX_FMT_DRM_PRIME {
+            let download_start_at = Instant::now();
+            let ret = unsafe {
+                ffi::av_hwframe_transfer_data(sw_frame.as_mut_ptr(), frame.as_ptr(), 0)
             };
-            let data = unsafe { std::slice::from_raw_parts(packet.data, packet.size as usize) };
-            total_size += data.len();
+            assert!(ret >= 0, "download frame: {}", ffi::av_err2str(ret));
+            download_total_time += download_start_at.elapsed();
         }
+    };
+    for packet in packets.iter() {
+        codec_ctx.send_packet(Some(packet)).expect("send packet");
+        receive_frames(&mut codec_ctx, &mut on_frame);
     }
-    codec_ctx.send_frame(None).expect("send frame");
+    codec_ctx.send_packet(None).expect("send packet");
+    receive_frames(&mut codec_ctx, &mut on_frame);
+
+    let total_time = start_at.elapsed();
+    let decode_total_time = total_time - download_total_time;
+    println!("{num_decoded_frames} frames decoded for {decode_total_time:?}");
+    println!("1 frame for {:?}", decode_total_time / num_decoded_frames.max(1));
+    match decoded_span.duration(time_base) {
+        Some(video_duration) => println!(
+            "{video_duration:?} of video decoded at {:.1}x real time",
+            video_duration.as_secs_f64() / decode_total_time.as_secs_f64()
+        ),
+        None => println!("Decoded frames have no timestamps"),
+    }
+    if decoded_span.num_without_ts > 0 && decoded_span.range.is_some() {
+        println!("{} frames without a timestamp", decoded_span.num_without_ts);
+    }
+    if args.download {
+        println!("{num_decoded_frames} frames decoded and downloaded for {total_time:?}");
+        println!("1 frame for {:?}", total_time / num_decoded_frames.max(1));
+        println!("1 frame download for {:?}", download_total_time / num_decoded_frames.max(1));
+    }
+}
+
+/// Sum of the squared differences of the samples of two planes of 8-bit
+/// components, returns it with the number of compared samples. Only the first
+/// `row_len` bytes of the `num_rows` rows are compared, the rest of the
+/// linesizes is padding
+fn plane_squared_error(
+    original: (&[u8], usize),
+    decoded: (&[u8], usize),
+    row_len: usize,
+    num_rows: usize,
+) -> (u64, u64) {
+    let (original, original_linesize) = original;
+    let (decoded, decoded_linesize) = decoded;
+    let mut sum = 0;
+    for row in 0..num_rows {
+        let original_row = &original[row * original_linesize..][..row_len];
+        let decoded_row = &decoded[row * decoded_linesize..][..row_len];
+        sum += original_row
+            .iter()
+            .zip(decoded_row)
+            .map(|(&a, &b)| (a as i64 - b as i64).pow(2) as u64)
+            .sum::<u64>();
+    }
+    (sum, (row_len * num_rows) as u64)
+}
+
+/// PSNR in dB of 8-bit samples, infinite if they are identical
+fn psnr(squared_error: u64, num_samples: u64) -> f64 {
+    if squared_error == 0 {
+        return f64::INFINITY;
+    }
+    let mse = squared_error as f64 / num_samples as f64;
+    10.0 * (255.0 * 255.0 / mse).log10()
+}
+
+/// Encodes the frames, decodes them back and compares the decoded frames with
+/// the original ones, see `--verify`
+fn verify(args: &Args) {
+    let input = args.input.as_ref().map(|path| RawInput::read(args, path));
+    let (packets, time_base) = encode_frames(args, input.as_ref());
+    let total_size = packets.iter().map(|packet| packet.size as usize).sum::<usize>();
+    println!("{} frames encoded into {total_size} bytes", args.num_frames);
+
+    let codec = AVCodec::find_decoder_by_name(args.codec().rkmpp_name()).expect("codec not found");
+    let mut codec_ctx = AVCodecContext::new(&codec);
+    unsafe { (*codec_ctx.as_mut_ptr()).pkt_timebase = time_base };
+    set_thread_count(args, &mut codec_ctx);
+    open_codec(&mut codec_ctx);
+
+    let (width, height) = args.frame_size();
+    let mut original = alloc_frame(args);
+    let num_planes = used_planes(&original);
+    let mut row_lens = [0; 4];
+    let ret =
+        unsafe { ffi::av_image_fill_linesizes(row_lens.as_mut_ptr(), args.pixel_format, width) };
+    assert!(ret >= 0, "fill image linesizes: {}", ffi::av_err2str(ret));
+    let mut squared_errors = vec![(0u64, 0u64); num_planes];
+    // The decoder may output another pixel format, e.g. NV12 for YUV420P
+    let mut scaler = None;
+    let mut num_decoded_frames = 0u32;
+    let mut on_frame = |mut decoded: AVFrame| {
+        let i = frame::best_effort_timestamp(&decoded).unwrap_or(num_decoded_frames as i64);
+        num_decoded_frames += 1;
+        frame::apply_cropping(unsafe { &mut *decoded.as_mut_ptr() }, 0).expect("crop frame");
+        if (decoded.width, decoded.height) != (width, height) {
+            println!(
+                "Decoded frame #{i} is {}x{} instead of {width}x{height}",
+                decoded.width, decoded.height
+            );
+            std::process::exit(1);
+        }
+        if decoded.format == AV_PIX_FMT_DRM_PRIME {
+            let mut sw_frame = AVFrame::new();
+            let ret = unsafe {
+                ffi::av_hwframe_transfer_data(sw_frame.as_mut_ptr(), decoded.as_ptr(), 0)
+            };
+            assert!(ret >= 0, "download frame: {}", ffi::av_err2str(ret));
+            decoded = sw_frame;
+        }
+        let converted = (decoded.format != args.pixel_format).then(|| {
+            let scaler = scaler.get_or_insert_with(|| {
+                let flags = ffi::SWS_POINT as i32;
+                Scaler::new(width, height, decoded.format, width, height, args.pixel_format, flags)
+                    .expect("create scaler")
+            });
+            scaler.scale_frame(&decoded).expect("convert decoded frame")
+        });
+        let decoded = converted.as_deref().unwrap_or(&*decoded);
+
+        generate_frame(args, input.as_ref(), &mut original, i as usize);
+        for (plane, squared_error) in squared_errors.iter_mut().enumerate() {
+            let num_rows =
+                pixdesc::plane_height(args.pixel_format, height, plane).expect("plane height");
+            let (sum, num_samples) = plane_squared_error(
+                (original.plane(plane).expect("original plane"), original.linesize[plane] as usize),
+                (decoded.plane(plane).expect("decoded plane"), decoded.linesize[plane] as usize),
+                row_lens[plane] as usize,
+                num_rows as usize,
+            );
+            squared_error.0 += sum;
+            squared_error.1 += num_samples;
+        }
+    };
+    for packet in packets.iter() {
+        codec_ctx.send_packet(Some(packet)).expect("send packet");
+        receive_frames(&mut codec_ctx, &mut on_frame);
+    }
+    codec_ctx.send_packet(None).expect("send packet");
+    receive_frames(&mut codec_ctx, &mut on_frame);
+
+    println!("{num_decoded_frames} frames decoded");
+    let mut passed = num_decoded_frames == args.num_frames;
+    if !passed {
+        println!("Expected {} decoded frames", args.num_frames);
+    }
+    for (plane, &(squared_error, num_samples)) in squared_errors.iter().enumerate() {
+        let plane_psnr = psnr(squared_error, num_samples);
+        println!("Plane #{plane} PSNR: {plane_psnr:.2} dB");
+        if plane_psnr < args.min_psnr {
+            println!("Plane #{plane} PSNR is below {:.2} dB", args.min_psnr);
+            passed = false;
+        }
+    }
+    if !passed {
+        std::process::exit(1);
+    }
+}
+
+/// RGA scaler graph: the frames are uploaded into DRM buffers, scaled and
+/// downloaded back with `--download`
+fn new_scale_graph(args: &Args) -> Result<FilterGraph, String> {
+    let (width, height) = args.frame_size();
+    let (out_width, out_height) = args.output_size.unwrap_or((width, height));
+    let out_format = pixdesc::pix_fmt_name(args.output_pixel_format).unwrap_or_default();
+    let mut filters = format!("hwupload,scale_rkrga=w={out_width}:h={out_height}:format={out_format}");
+    if args.download {
+        filters.push_str(&format!(",hwdownload,format={out_format}"));
+    }
+    let hw_device = hwdevice::create_rkmpp()?;
+    FilterGraph::video(&filters, width, height, args.pixel_format, ra(1, 25), Some(&hw_device))
+}
+
+fn scale(args: &Args) {
+    let input = args.input.as_ref().map(|path| RawInput::read(args, path));
+    let mut graph = new_scale_graph(args).unwrap_or_else(|e| panic!("create filter graph: {e}"));
+    let mut frame = alloc_frame(args);
+    let (width, height) = args.frame_size();
+    let (out_width, out_height) = args.output_size.unwrap_or((width, height));
+    let name = |pix_fmt| pixdesc::pix_fmt_name(pix_fmt).unwrap_or_default();
+    println!(
+        "Scaling {width}x{height} {} into {out_width}x{out_height} {}",
+        name(args.pixel_format),
+        name(args.output_pixel_format),
+    );
+
+    let start_at = Instant::now();
+    let mut gen_frame_total_time = Duration::ZERO;
+    let mut num_scaled_frames = 0u32;
+    let mut pull_frames = |graph: &mut FilterGraph| {
+        while let PulledFrame::Frame(_) = graph.pull_frame().expect("pull frame") {
+            num_scaled_frames += 1;
+        }
+    };
+    for i in 0..args.num_frames as usize {
+        let gen_frame_start_at = Instant::now();
+        generate_frame(args, input.as_ref(), &mut frame, i);
+        gen_frame_total_time += gen_frame_start_at.elapsed();
+
+        graph.push_frame(Some(&frame)).expect("push frame");
+        pull_frames(&mut graph);
+    }
+    graph.push_frame(None).expect("push frame");
+    pull_frames(&mut graph);
+
+    let scale_time = start_at.elapsed() - gen_frame_total_time;
+    println!("{num_scaled_frames} frames scaled for {scale_time:?}");
+    println!("1 frame for {:?}", scale_time / num_scaled_frames.max(1));
+    println!("FPS: {:.2}", num_scaled_frames as f64 / scale_time.as_secs_f64());
+}
+
+fn print_color_properties(frame: &ffi::AVFrame) {
+    let name = |name: Option<String>| name.unwrap_or_else(|| "unknown".to_string());
+    println!(
+        "Color space: {}, range: {}, primaries: {}, transfer: {}",
+        name(csp::colorspace_name(frame.colorspace)),
+        name(csp::color_range_name(frame.color_range)),
+        name(csp::color_primaries_name(frame.color_primaries)),
+        name(csp::color_trc_name(frame.color_trc)),
+    );
+}
+
+/// Receives all the frames available at the moment
+fn receive_frames(codec_ctx: &mut AVCodecContext, mut on_frame: impl FnMut(AVFrame)) {
     loop {
-        let packet = match codec_ctx.receive_packet() {
-            Ok(packet) => packet,
-            Err(RsmpegError::EncoderDrainError) | Err(RsmpegError::EncoderFlushedError) => break,
+        let frame = match codec_ctx.receive_frame() {
+            Ok(frame) => frame,
+            Err(RsmpegError::DecoderDrainError) | Err(RsmpegError::DecoderFlushedError) => break,
             Err(e) => panic!("{e}"),
         };
-        let data = unsafe { std::slice::from_raw_parts(packet.data, packet.size as usize) };
-        total_size += data.len();
+        on_frame(frame);
+    }
+}
+
+/// Whether [fill_test_pattern] can generate frames of the pixel format: only
+/// formats with byte-sized components are supported
+fn test_pattern_supported(pix_fmt: ffi::AVPixelFormat) -> bool {
+    let Some(desc) = pixdesc::descriptor(pix_fmt) else {
+        return false;
+    };
+    let unsupported_flags = ffi::AV_PIX_FMT_FLAG_PAL
+        | ffi::AV_PIX_FMT_FLAG_BITSTREAM
+        | ffi::AV_PIX_FMT_FLAG_HWACCEL
+        | ffi::AV_PIX_FMT_FLAG_BAYER
+        | ffi::AV_PIX_FMT_FLAG_FLOAT;
+    desc.flags & unsupported_flags as u64 == 0
+        && desc.comp[..desc.nb_components as usize]
+            .iter()
+            .all(|comp| comp.depth == 8 && comp.shift == 0)
+}
+
+/// Fills the frame with the test pattern. The gradient uses the dedicated
+/// generators for the common formats and the pixel format descriptor for the
+/// others, `seed` only applies to the random patterns
+fn fill_test_pattern(frame: &mut ffi::AVFrame, i: usize, pattern: Pattern, seed: u64) {
+    match (pattern, frame.format) {
+        (Pattern::Gradient, AV_PIX_FMT_YUV420P) => generate_yuv420p_frame(frame, i),
+        (Pattern::Gradient, AV_PIX_FMT_UYVY422) => generate_uyvy422_frame(frame, i),
+        (Pattern::Gradient, _) => generate_generic_frame(frame, i),
+        (Pattern::Noise, _) => generate_noise_frame(frame, i, seed),
+    }
+}
+
+/// Fills the planes with pseudo-random bytes, the frame only depends on the
+/// seed and its index, so the frames are the same across runs
+fn generate_noise_frame(frame: &mut ffi::AVFrame, i: usize, seed: u64) {
+    // SplitMix64
+    const GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+    let mut state = seed ^ (i as u64).wrapping_mul(GAMMA);
+    for plane in 0..used_planes(frame) {
+        let data = frame.plane_mut(plane).expect("frame plane");
+        for chunk in data.chunks_mut(8) {
+            state = state.wrapping_add(GAMMA);
+            let mut z = state;
+            z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+            z ^= z >> 31;
+            chunk.copy_from_slice(&z.to_le_bytes()[..chunk.len()]);
+        }
+    }
+}
+
+/// Generates a frame of any pixel format supported by [test_pattern_supported]
+fn generate_generic_frame(frame: &mut ffi::AVFrame, i: usize) {
+    let desc = pixdesc::descriptor(frame.format).expect("pixel format descriptor");
+    let is_rgb = desc.flags & ffi::AV_PIX_FMT_FLAG_RGB as u64 != 0;
+    let width = frame.width as usize;
+    let height = frame.height as usize;
+    let linesize = frame.linesize;
+
+    for (c, comp) in desc.comp[..desc.nb_components as usize].iter().enumerate() {
+        // Components 1 and 2 of YUV formats are the subsampled chroma
+        let (log2_w, log2_h) = if !is_rgb && (c == 1 || c == 2) {
+            (desc.log2_chroma_w, desc.log2_chroma_h)
+        } else {
+            (0, 0)
+        };
+        let plane = comp.plane as usize;
+        let plane_linesize = linesize[plane] as usize;
+        let data = frame.plane_mut(plane).expect("component plane");
+        for y in 0..height.div_ceil(1 << log2_h) {
+            for x in 0..width.div_ceil(1 << log2_w) {
+                let value = match c {
+                    0 => x + y + i * 3,
+                    1 => 128 + y + i * 2,
+                    2 => 64 + x + i * 5,
+                    _ => 255,
+                };
+                data[y * plane_linesize + x * comp.step as usize + comp.offset as usize] = value as u8;
+            }
+        }
     }
-    let encode_total_time = start_at.elapsed() - gen_frame_total_time;
-    println!("{} frames processed for {:?}", args.num_frames, start_at.elapsed());
-    println!("{} frames encoded/decodec for {:?}", args.num_frames, encode_total_time);
-    println!("1 frame for {:?}", encode_total_time / args.num_frames);
-    println!("Total encoded size: {total_size}");
 }
 
 #[inline(always)]
-fn generate_yuv420p_frame(frame: &mut AVFrame, i: usize) {
-    // assert!(
-    //     frame.linesize.len() == 3,
-    //     "YUV420P pixel format must have 3 planes but had {}", frame.linesize.len()
-    // );
+fn generate_yuv420p_frame(frame: &mut ffi::AVFrame, i: usize) {
+    let num_planes = used_planes(frame);
+    assert!(
+        num_planes == 3 && frame.linesize[..3].iter().all(|&linesize| linesize > 0),
+        "YUV420P pixel format must have 3 planes but had {num_planes}: {:?}", frame.linesize
+    );
 
     let width = frame.width as usize;
     let height = frame.height as usize;
@@ -166,36 +1355,41 @@ fn generate_yuv420p_frame(frame: &mut AVFrame, i: usize) {
     let linesize_y = linesize[0] as usize;
     let linesize_cb = linesize[1] as usize;
     let linesize_cr = linesize[2] as usize;
-    let data = frame.data;
-    let y_data = unsafe { std::slice::from_raw_parts_mut(data[0], height * linesize_y) };
-    let cb_data = unsafe { std::slice::from_raw_parts_mut(data[1], height / 2 * linesize_cb) };
-    let cr_data = unsafe { std::slice::from_raw_parts_mut(data[2], height / 2 * linesize_cr) };
 
+    let y_data = frame.plane_mut(0).expect("Y plane");
     for y in 0..height {
         for x in 0..width {
             y_data[y * linesize_y + x] = (x + y + i * 3) as u8;
         }
     }
 
+    let cb_data = frame.plane_mut(1).expect("Cb plane");
     for y in 0..height / 2 {
         for x in 0..width / 2 {
             cb_data[y * linesize_cb + x] = (128 + y + i * 2) as u8;
+        }
+    }
+
+    let cr_data = frame.plane_mut(2).expect("Cr plane");
+    for y in 0..height / 2 {
+        for x in 0..width / 2 {
             cr_data[y * linesize_cr + x] = (64 + x + i * 5) as u8;
         }
     }
 }
 
 #[inline(always)]
-fn generate_uyvy422_frame(frame: &mut AVFrame, i: usize) {
-    // assert!(
-    //     frame.linesize.len() == 1,
-    //     "uyvy422 pixel format must have 1 planes but had {}", frame.linesize.len()
-    // );
+fn generate_uyvy422_frame(frame: &mut ffi::AVFrame, i: usize) {
+    let num_planes = used_planes(frame);
+    assert!(
+        num_planes == 1 && frame.linesize[0] > 0,
+        "UYVY422 pixel format must have 1 plane but had {num_planes}: {:?}", frame.linesize
+    );
 
     let width = frame.width as usize;
     let height = frame.height as usize;
     let linesize = frame.linesize[0] as usize;
-    let data = unsafe { std::slice::from_raw_parts_mut(frame.data[0], height * linesize) };
+    let data = frame.plane_mut(0).expect("packed plane");
 
     for y in 0..height {
         for x in 0..width {
@@ -207,3 +1401,78 @@ fn generate_uyvy422_frame(frame: &mut AVFrame, i: usize) {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn alloc_test_frame() -> AVFrame {
+        let mut frame = AVFrame::new();
+        frame.set_format(AV_PIX_FMT_YUV420P);
+        frame.set_width(64);
+        frame.set_height(48);
+        frame.alloc_buffer().expect("alloc frame buffer");
+        frame
+    }
+
+    #[test]
+    fn test_noise_is_seeded() {
+        let frames = [(1, 0), (1, 0), (2, 0), (1, 1)].map(|(seed, i)| {
+            let mut frame = alloc_test_frame();
+            let raw_frame = unsafe { &mut *frame.as_mut_ptr() };
+            fill_test_pattern(raw_frame, i, Pattern::Noise, seed);
+            raw_frame.plane(0).unwrap().to_vec()
+        });
+        assert_eq!(frames[0], frames[1]);
+        assert_ne!(frames[0], frames[2]);
+        assert_ne!(frames[0], frames[3]);
+    }
+
+    #[test]
+    fn test_frame_pool() {
+        let mut pool = FramePool::new(2, alloc_test_frame);
+        let frame = pool.get();
+        let data = frame.data[0];
+        pool.put(frame);
+        assert_eq!(pool.get().data[0], data);
+
+        let frames = [pool.get(), pool.get(), pool.get()];
+        for frame in frames {
+            pool.put(frame);
+        }
+        assert_eq!(pool.free.len(), 2);
+    }
+
+    #[test]
+    fn test_decoded_span() {
+        let mut span = DecodedSpan::default();
+        let mut frame = AVFrame::new();
+        span.add(&frame);
+        assert_eq!(span.duration(ra(1, 25)), None);
+
+        for ts in [2, 0, 1] {
+            unsafe {
+                (*frame.as_mut_ptr()).best_effort_timestamp = ts;
+                (*frame.as_mut_ptr()).duration = 1;
+            }
+            span.add(&frame);
+        }
+        assert_eq!(span.duration(ra(1, 25)), Some(Duration::from_millis(120)));
+        assert_eq!(span.num_without_ts, 1);
+    }
+
+    #[test]
+    fn test_psnr() {
+        // 2x2 plane with a padded linesize
+        let original = [10, 20, 0, 30, 40, 0];
+        assert_eq!(plane_squared_error((&original, 3), (&[10, 20, 30, 40], 2), 2, 2), (0, 4));
+        assert_eq!(psnr(0, 4), f64::INFINITY);
+
+        let (squared_error, num_samples) =
+            plane_squared_error((&original, 3), (&[12, 20, 30, 38], 2), 2, 2);
+        assert_eq!((squared_error, num_samples), (8, 4));
+        // MSE of 2
+        let expected = 10.0 * (255.0f64 * 255.0 / 2.0).log10();
+        assert!((psnr(squared_error, num_samples) - expected).abs() < 1e-9);
+    }
+}
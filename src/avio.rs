@@ -0,0 +1,279 @@
+use crate::ffi::{self, AVIOContext};
+use std::ffi::{c_int, c_void, CString};
+use std::io::{self, SeekFrom};
+use std::ptr::{self, NonNull};
+use std::slice;
+
+/// Callback filling the buffer with data, returning the number of bytes read
+/// (0 at the end of the stream)
+pub type ReadFn = dyn FnMut(&mut [u8]) -> io::Result<usize> + Send;
+/// Callback consuming all the data of the buffer
+pub type WriteFn = dyn FnMut(&[u8]) -> io::Result<()> + Send;
+/// Callback seeking the stream, returning the new position
+pub type SeekFn = dyn FnMut(SeekFrom) -> io::Result<u64> + Send;
+
+struct Callbacks {
+    read: Option<Box<ReadFn>>,
+    write: Option<Box<WriteFn>>,
+    seek: Option<Box<SeekFn>>,
+}
+
+/// An `AVIOContext` backed by Rust callbacks, e.g. to mux into memory.
+///
+/// Assign [`CustomIO::as_mut_ptr`] to the `pb` field of a format context and
+/// set `AVFMT_FLAG_CUSTOM_IO` in its flags. The format context must be freed
+/// before the `CustomIO`.
+pub struct CustomIO {
+    ctx: NonNull<AVIOContext>,
+    _callbacks: Box<Callbacks>,
+}
+
+impl CustomIO {
+    /// Allocate an I/O context with a buffer of `buffer_size` bytes. The
+    /// context is writable if `write` is given.
+    pub fn new(
+        buffer_size: usize,
+        read: Option<Box<ReadFn>>,
+        write: Option<Box<WriteFn>>,
+        seek: Option<Box<SeekFn>>,
+    ) -> Result<Self, String> {
+        let buffer = unsafe { ffi::av_malloc(buffer_size) };
+        if buffer.is_null() {
+            return Err(ffi::av_err2str(ffi::AVERROR(ffi::ENOMEM)));
+        }
+        let write_flag = write.is_some() as c_int;
+        let mut callbacks = Box::new(Callbacks { read, write, seek });
+        let ctx = unsafe {
+            ffi::avio_alloc_context(
+                buffer.cast(),
+                buffer_size as c_int,
+                write_flag,
+                (&mut *callbacks as *mut Callbacks).cast(),
+                callbacks.read.is_some().then_some(read_trampoline as _),
+                callbacks.write.is_some().then_some(write_trampoline as _),
+                callbacks.seek.is_some().then_some(seek_trampoline as _),
+            )
+        };
+        let Some(ctx) = NonNull::new(ctx) else {
+            unsafe { ffi::av_free(buffer) };
+            return Err(ffi::av_err2str(ffi::AVERROR(ffi::ENOMEM)));
+        };
+        Ok(Self {
+            ctx,
+            _callbacks: callbacks,
+        })
+    }
+
+    /// Allocate a write-only I/O context, see [`CustomIO::new`].
+    pub fn writer(
+        buffer_size: usize,
+        write: impl FnMut(&[u8]) -> io::Result<()> + Send + 'static,
+    ) -> Result<Self, String> {
+        Self::new(buffer_size, None, Some(Box::new(write)), None)
+    }
+
+    /// Allocate a read-only I/O context, see [`CustomIO::new`].
+    pub fn reader(
+        buffer_size: usize,
+        read: impl FnMut(&mut [u8]) -> io::Result<usize> + Send + 'static,
+    ) -> Result<Self, String> {
+        Self::new(buffer_size, Some(Box::new(read)), None, None)
+    }
+
+    pub fn as_ptr(&self) -> *const AVIOContext {
+        self.ctx.as_ptr()
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut AVIOContext {
+        self.ctx.as_ptr()
+    }
+}
+
+impl Drop for CustomIO {
+    fn drop(&mut self) {
+        let mut ctx = self.ctx.as_ptr();
+        unsafe {
+            if (*ctx).write_flag != 0 {
+                ffi::avio_flush(ctx);
+            }
+            // The buffer may have been reallocated by FFmpeg
+            ffi::av_freep(std::ptr::from_mut(&mut (*ctx).buffer).cast());
+            ffi::avio_context_free(&mut ctx);
+        }
+    }
+}
+
+/// Open a file (or URL) for writing, truncating it, e.g. as the `pb` of an
+/// output format context without the `AVFMT_NOFILE` flag.
+///
+/// The context must be closed with [`close`], also when writing fails.
+pub fn open_write(path: &str) -> Result<*mut AVIOContext, String> {
+    let path = CString::new(path).map_err(|e| e.to_string())?;
+    let mut ctx = ptr::null_mut();
+    let ret = unsafe {
+        ffi::avio_open2(
+            &mut ctx,
+            path.as_ptr(),
+            ffi::AVIO_FLAG_WRITE as c_int,
+            ptr::null(),
+            ptr::null_mut(),
+        )
+    };
+    if ret < 0 {
+        return Err(ffi::av_err2str(ret));
+    }
+    Ok(ctx)
+}
+
+/// Flush and close a context opened with [`open_write`], setting it to null.
+///
+/// The context is always closed, the error of a failed write or of the
+/// final flush is returned afterwards. Does nothing if the context is null.
+///
+/// # Safety
+/// `ctx` must be null or point to a context opened with [`open_write`].
+pub unsafe fn close(ctx: &mut *mut AVIOContext) -> Result<(), String> {
+    if ctx.is_null() {
+        return Ok(());
+    }
+    // Errors of `avio_write` are only recorded in the context
+    let error = (**ctx).error;
+    let ret = ffi::avio_closep(ctx);
+    if error < 0 {
+        return Err(ffi::av_err2str(error));
+    }
+    if ret < 0 {
+        return Err(ffi::av_err2str(ret));
+    }
+    Ok(())
+}
+
+fn io_error(e: io::Error) -> c_int {
+    ffi::AVERROR(e.raw_os_error().map_or(ffi::EIO, |errno| errno as u32))
+}
+
+unsafe extern "C" fn read_trampoline(opaque: *mut c_void, buf: *mut u8, buf_size: c_int) -> c_int {
+    let callbacks = &mut *opaque.cast::<Callbacks>();
+    let read = callbacks.read.as_mut().expect("read callback");
+    match read(slice::from_raw_parts_mut(buf, buf_size as usize)) {
+        Ok(0) => ffi::AVERROR_EOF,
+        Ok(len) => len as c_int,
+        Err(e) => io_error(e),
+    }
+}
+
+unsafe extern "C" fn write_trampoline(
+    opaque: *mut c_void,
+    buf: *const u8,
+    buf_size: c_int,
+) -> c_int {
+    let callbacks = &mut *opaque.cast::<Callbacks>();
+    let write = callbacks.write.as_mut().expect("write callback");
+    match write(slice::from_raw_parts(buf, buf_size as usize)) {
+        Ok(()) => buf_size,
+        Err(e) => io_error(e),
+    }
+}
+
+unsafe extern "C" fn seek_trampoline(opaque: *mut c_void, offset: i64, whence: c_int) -> i64 {
+    let callbacks = &mut *opaque.cast::<Callbacks>();
+    let seek = callbacks.seek.as_mut().expect("seek callback");
+    let whence = whence as u32 & !ffi::AVSEEK_FORCE;
+    let result = if whence == ffi::AVSEEK_SIZE {
+        // Find the size by seeking to the end and back
+        seek(SeekFrom::Current(0)).and_then(|pos| {
+            let size = seek(SeekFrom::End(0))?;
+            seek(SeekFrom::Start(pos))?;
+            Ok(size)
+        })
+    } else {
+        let pos = match whence {
+            ffi::SEEK_SET => SeekFrom::Start(offset as u64),
+            ffi::SEEK_CUR => SeekFrom::Current(offset),
+            ffi::SEEK_END => SeekFrom::End(offset),
+            _ => return ffi::AVERROR(ffi::EINVAL) as i64,
+        };
+        seek(pos)
+    };
+    match result {
+        Ok(pos) => pos as i64,
+        Err(e) => io_error(e) as i64,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::io::{Cursor, Read};
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_read() {
+        let mut input = Cursor::new(b"custom io".to_vec());
+        let mut io = CustomIO::reader(4, move |buf| input.read(buf)).unwrap();
+        let mut buf = [0u8; 16];
+        let len = unsafe { ffi::avio_read(io.as_mut_ptr(), buf.as_mut_ptr(), buf.len() as c_int) };
+        assert_eq!(&buf[..len as usize], b"custom io");
+    }
+
+    #[test]
+    fn test_mux_to_memory() {
+        let output = Arc::new(Mutex::new(vec![]));
+        let write_output = output.clone();
+        let mut io = CustomIO::writer(4096, move |buf| {
+            write_output.lock().unwrap().extend_from_slice(buf);
+            Ok(())
+        })
+        .unwrap();
+
+        unsafe {
+            let mut fmt_ctx = ptr::null_mut();
+            let ret = ffi::avformat_alloc_output_context2(
+                &mut fmt_ctx,
+                ptr::null(),
+                c"matroska".as_ptr(),
+                ptr::null(),
+            );
+            if ret < 0 {
+                // The muxer is not enabled in this FFmpeg build
+                return;
+            }
+            let stream = ffi::avformat_new_stream(fmt_ctx, ptr::null());
+            let par = &mut *(*stream).codecpar;
+            par.codec_type = ffi::AVMEDIA_TYPE_VIDEO;
+            par.codec_id = ffi::AV_CODEC_ID_MJPEG;
+            par.width = 64;
+            par.height = 48;
+            (*stream).time_base = ffi::AVRational { num: 1, den: 25 };
+            (*fmt_ctx).pb = io.as_mut_ptr();
+            (*fmt_ctx).flags |= ffi::AVFMT_FLAG_CUSTOM_IO as c_int;
+
+            assert_eq!(ffi::avformat_write_header(fmt_ctx, ptr::null_mut()), 0);
+            assert_eq!(ffi::av_write_trailer(fmt_ctx), 0);
+            ffi::avformat_free_context(fmt_ctx);
+        }
+        drop(io);
+
+        // EBML magic of Matroska files
+        assert_eq!(output.lock().unwrap()[..4], [0x1A, 0x45, 0xDF, 0xA3]);
+    }
+
+    #[test]
+    fn test_write_file() {
+        let path =
+            std::env::temp_dir().join(format!("rusty_ffmpeg_avio_{}.bin", std::process::id()));
+        let path = path.to_str().unwrap();
+        let data = b"written with avio";
+        let mut ctx = open_write(path).unwrap();
+        unsafe {
+            ffi::avio_write(ctx, data.as_ptr(), data.len() as c_int);
+            close(&mut ctx).unwrap();
+        }
+        assert!(ctx.is_null());
+        assert_eq!(std::fs::read(path).unwrap(), data);
+        std::fs::remove_file(path).unwrap();
+
+        assert!(open_write("/nonexistent/rusty_ffmpeg_avio.bin").is_err());
+        assert_eq!(unsafe { close(&mut ptr::null_mut()) }, Ok(()));
+    }
+}
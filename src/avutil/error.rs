@@ -1,9 +1,15 @@
-use std::ffi::c_char;
-use std::ffi::c_int;
-use std::ffi::CStr;
+use alloc::string::String;
+use core::ffi::c_char;
+use core::ffi::c_int;
+use core::ffi::CStr;
 use super::common::MKTAG;
 use crate::ffi;
 
+/// Negative FFmpeg error code of an errno value.
+///
+/// The errno values differ between platforms (e.g. `EAGAIN` is 11 on Linux
+/// and 35 on macOS and the BSDs), use the `ffi::E*` constants which are
+/// generated from the `<errno.h>` of the target.
 #[allow(non_snake_case)]
 pub const fn AVERROR(e: u32) -> c_int {
     -(e as c_int)
@@ -91,4 +97,12 @@ mod test {
         assert_eq!(&av_err2str(AVERROR(ffi::ENOMEM)), "Cannot allocate memory");
         assert_eq!(&av_err2str(AVERROR_EOF), "End of file");
     }
+
+    #[test]
+    fn test_errno_matches_target() {
+        assert_eq!(ffi::EAGAIN as c_int, libc::EAGAIN);
+        assert_eq!(ffi::ENOMEM as c_int, libc::ENOMEM);
+        assert_eq!(ffi::EINVAL as c_int, libc::EINVAL);
+        assert_eq!(ffi::EBUSY as c_int, libc::EBUSY);
+    }
 }
@@ -2,7 +2,7 @@
 /// in linked library. So we need this.
 /// Ref: https://github.com/rust-lang/rust-bindgen/issues/1344
 use crate::ffi::AVRational;
-use std::ffi::{c_double, c_int};
+use core::ffi::{c_double, c_int};
 
 /// Create an AVRational.
 ///
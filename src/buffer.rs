@@ -0,0 +1,78 @@
+use crate::ffi::{self, AVBufferRef};
+use core::ptr::NonNull;
+
+/// An owned reference to a reference counted `AVBuffer`.
+///
+/// Cloning creates a new reference to the same buffer, dropping releases the
+/// reference and frees the buffer once the last reference is gone.
+#[derive(Debug)]
+pub struct Buffer {
+    ptr: NonNull<AVBufferRef>,
+}
+
+impl Buffer {
+    /// Allocate a new buffer of `size` bytes, `None` on allocation failure.
+    pub fn new(size: usize) -> Option<Self> {
+        unsafe { Self::from_raw(ffi::av_buffer_alloc(size)) }
+    }
+
+    /// Take ownership of a buffer reference, `None` if `ptr` is null.
+    ///
+    /// # Safety
+    /// `ptr` must be a valid buffer reference which is not freed elsewhere.
+    pub unsafe fn from_raw(ptr: *mut AVBufferRef) -> Option<Self> {
+        NonNull::new(ptr).map(|ptr| Self { ptr })
+    }
+
+    /// Release the ownership of the reference without unreferencing it.
+    pub fn into_raw(self) -> *mut AVBufferRef {
+        let ptr = self.ptr.as_ptr();
+        core::mem::forget(self);
+        ptr
+    }
+
+    pub fn as_ptr(&self) -> *const AVBufferRef {
+        self.ptr.as_ptr()
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut AVBufferRef {
+        self.ptr.as_ptr()
+    }
+
+    /// Number of references to the underlying buffer
+    pub fn ref_count(&self) -> usize {
+        unsafe { ffi::av_buffer_get_ref_count(self.as_ptr()) as usize }
+    }
+}
+
+impl Clone for Buffer {
+    fn clone(&self) -> Self {
+        unsafe { Self::from_raw(ffi::av_buffer_ref(self.as_ptr())) }
+            .expect("Cannot allocate buffer reference")
+    }
+}
+
+impl Drop for Buffer {
+    fn drop(&mut self) {
+        let mut ptr = self.ptr.as_ptr();
+        unsafe { ffi::av_buffer_unref(&mut ptr) };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_clone_shares_buffer() {
+        let buffer = Buffer::new(16).unwrap();
+        assert_eq!(buffer.ref_count(), 1);
+        let cloned = buffer.clone();
+        assert_eq!(buffer.ref_count(), 2);
+        assert_eq!(unsafe { (*buffer.as_ptr()).data }, unsafe {
+            (*cloned.as_ptr()).data
+        });
+        drop(cloned);
+        assert_eq!(buffer.ref_count(), 1);
+    }
+}
@@ -0,0 +1,559 @@
+use crate::ffi::{
+    self, AVCodec, AVCodecContext, AVCodecParameters, AVDictionary, AVFrame, AVPixelFormat,
+};
+use crate::packet::Packet;
+use crate::pixdesc;
+use std::ffi::c_int;
+use std::ops::{Deref, DerefMut};
+use std::ptr::{self, NonNull};
+use std::slice;
+use std::time::Duration;
+
+/// Pixel formats supported by a codec, empty if the codec doesn't declare
+/// them (i.e. it accepts any format or it is not a video codec).
+pub fn supported_pix_fmts(codec: &AVCodec) -> Vec<AVPixelFormat> {
+    unsafe { pix_fmt_list(codec.pix_fmts) }.to_vec()
+}
+
+/// Capabilities of a codec, a combination of the `AV_CODEC_CAP_*` flags.
+pub fn capabilities(codec: &AVCodec) -> u32 {
+    codec.capabilities as u32
+}
+
+/// Check if a codec is backed by hardware, either fully
+/// (`AV_CODEC_CAP_HARDWARE`) or partially (`AV_CODEC_CAP_HYBRID`).
+pub fn is_hardware(codec: &AVCodec) -> bool {
+    capabilities(codec) & (ffi::AV_CODEC_CAP_HARDWARE | ffi::AV_CODEC_CAP_HYBRID) != 0
+}
+
+/// Make a decoder export the motion vectors of the frames, see
+/// [`crate::frame::motion_vectors`]. Must be called before opening the
+/// decoder.
+pub fn enable_export_mvs(ctx: &mut AVCodecContext) {
+    ctx.export_side_data |= ffi::AV_CODEC_EXPORT_DATA_MVS as i32;
+}
+
+/// Codec-specific extra data of a context, e.g. the SPS and PPS of an H.264
+/// encoder opened with `AV_CODEC_FLAG_GLOBAL_HEADER`. Empty if there is none.
+pub fn extradata(ctx: &AVCodecContext) -> &[u8] {
+    if ctx.extradata.is_null() || ctx.extradata_size <= 0 {
+        return &[];
+    }
+    unsafe { slice::from_raw_parts(ctx.extradata, ctx.extradata_size as usize) }
+}
+
+/// Replace the extra data of a context, e.g. of a decoder of a stream with
+/// global headers. The data is copied into a buffer padded with
+/// `AV_INPUT_BUFFER_PADDING_SIZE` zeroed bytes, as required by FFmpeg.
+pub fn set_extradata(ctx: &mut AVCodecContext, data: &[u8]) -> Result<(), String> {
+    let size =
+        c_int::try_from(data.len()).map_err(|_| ffi::av_err2str(ffi::AVERROR(ffi::EINVAL)))?;
+    let extradata =
+        unsafe { ffi::av_mallocz(data.len() + ffi::AV_INPUT_BUFFER_PADDING_SIZE as usize) };
+    if extradata.is_null() {
+        return Err(ffi::av_err2str(ffi::AVERROR(ffi::ENOMEM)));
+    }
+    unsafe {
+        ptr::copy_nonoverlapping(data.as_ptr(), extradata.cast(), data.len());
+        ffi::av_freep(ptr::from_mut(&mut ctx.extradata).cast());
+    }
+    ctx.extradata = extradata.cast();
+    ctx.extradata_size = size;
+    Ok(())
+}
+
+/// Fill codec parameters from a codec context, e.g. to set up the stream of
+/// an encoder output.
+///
+/// # Safety
+/// `par` and `ctx` must point to valid codec parameters and codec context.
+pub unsafe fn parameters_from_context(
+    par: *mut AVCodecParameters,
+    ctx: *const AVCodecContext,
+) -> Result<(), String> {
+    let ret = ffi::avcodec_parameters_from_context(par, ctx);
+    if ret < 0 {
+        return Err(ffi::av_err2str(ret));
+    }
+    Ok(())
+}
+
+/// Fill a codec context from codec parameters, e.g. to set up a decoder of
+/// an input stream.
+///
+/// # Safety
+/// `ctx` and `par` must point to valid codec context and codec parameters.
+pub unsafe fn parameters_to_context(
+    ctx: *mut AVCodecContext,
+    par: *const AVCodecParameters,
+) -> Result<(), String> {
+    let ret = ffi::avcodec_parameters_to_context(ctx, par);
+    if ret < 0 {
+        return Err(ffi::av_err2str(ret));
+    }
+    Ok(())
+}
+
+/// Open a codec context, retrying with a backoff while the device is busy.
+///
+/// The rkmpp codecs can fail to open with `EBUSY` or `EAGAIN` when the MPP
+/// device is used by other processes. Such failures are retried up to
+/// `attempts` times in total, doubling `delay` after each attempt; other
+/// errors are returned immediately.
+///
+/// # Safety
+/// The arguments must be valid for `avcodec_open2`.
+pub unsafe fn open_with_retry(
+    ctx: *mut AVCodecContext,
+    codec: *const AVCodec,
+    options: *mut *mut AVDictionary,
+    attempts: u32,
+    delay: Duration,
+) -> Result<(), String> {
+    retry_busy(attempts, delay, || ffi::avcodec_open2(ctx, codec, options))
+}
+
+fn retry_busy(
+    attempts: u32,
+    mut delay: Duration,
+    mut op: impl FnMut() -> c_int,
+) -> Result<(), String> {
+    let busy_errors = [ffi::AVERROR(ffi::EBUSY), ffi::AVERROR(ffi::EAGAIN)];
+    let mut attempt = 1;
+    loop {
+        let ret = op();
+        if ret >= 0 {
+            return Ok(());
+        }
+        if !busy_errors.contains(&ret) {
+            return Err(ffi::av_err2str(ret));
+        }
+        if attempt >= attempts {
+            return Err(format!(
+                "{} (gave up after {attempt} attempts)",
+                ffi::av_err2str(ret)
+            ));
+        }
+        std::thread::sleep(delay);
+        delay *= 2;
+        attempt += 1;
+    }
+}
+
+/// An owned `AVCodecContext`, freed with `avcodec_free_context` when dropped.
+///
+/// A thin convenience over the send/receive API for the users not relying on
+/// a higher level wrapper, the fields are accessible through `Deref`.
+#[derive(Debug)]
+pub struct CodecContext {
+    ptr: NonNull<AVCodecContext>,
+}
+
+impl CodecContext {
+    /// Allocate a context for `codec` with its default values, `None` on
+    /// allocation failure.
+    pub fn new(codec: &AVCodec) -> Option<Self> {
+        NonNull::new(unsafe { ffi::avcodec_alloc_context3(codec) }).map(|ptr| Self { ptr })
+    }
+
+    pub fn as_ptr(&self) -> *const AVCodecContext {
+        self.ptr.as_ptr()
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut AVCodecContext {
+        self.ptr.as_ptr()
+    }
+
+    /// Open the codec the context was allocated for, the parameters (size,
+    /// pixel format, time base, ...) must be set before.
+    pub fn open(&mut self) -> Result<(), String> {
+        let ret = unsafe { ffi::avcodec_open2(self.as_mut_ptr(), ptr::null(), ptr::null_mut()) };
+        if ret < 0 {
+            return Err(ffi::av_err2str(ret));
+        }
+        Ok(())
+    }
+
+    /// Send a frame to the encoder, `None` flushes it.
+    ///
+    /// Fails with `EAGAIN` when the packets must be received first.
+    pub fn send_frame(&mut self, frame: Option<&AVFrame>) -> Result<(), String> {
+        let frame = frame.map_or(ptr::null(), ptr::from_ref);
+        let ret = unsafe { ffi::avcodec_send_frame(self.as_mut_ptr(), frame) };
+        if ret < 0 {
+            return Err(ffi::av_err2str(ret));
+        }
+        Ok(())
+    }
+
+    /// Receive an encoded packet.
+    pub fn receive_packet(&mut self) -> Result<ReceivedPacket, String> {
+        let mut packet = Packet::new().ok_or_else(|| ffi::av_err2str(ffi::AVERROR(ffi::ENOMEM)))?;
+        match unsafe { ffi::avcodec_receive_packet(self.as_mut_ptr(), packet.as_mut_ptr()) } {
+            ffi::AVERROR_EOF => Ok(ReceivedPacket::Eof),
+            ret if ret == ffi::AVERROR(ffi::EAGAIN) => Ok(ReceivedPacket::Again),
+            ret if ret < 0 => Err(ffi::av_err2str(ret)),
+            _ => Ok(ReceivedPacket::Packet(packet)),
+        }
+    }
+}
+
+/// Outcome of [`CodecContext::receive_packet`].
+#[derive(Debug)]
+pub enum ReceivedPacket {
+    Packet(Packet),
+    /// More frames must be sent first (`EAGAIN`)
+    Again,
+    /// The encoder is flushed and has no more packets (`EOF`)
+    Eof,
+}
+
+impl Deref for CodecContext {
+    type Target = AVCodecContext;
+
+    fn deref(&self) -> &AVCodecContext {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl DerefMut for CodecContext {
+    fn deref_mut(&mut self) -> &mut AVCodecContext {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl Drop for CodecContext {
+    fn drop(&mut self) {
+        let mut ptr = self.ptr.as_ptr();
+        unsafe { ffi::avcodec_free_context(&mut ptr) };
+    }
+}
+
+/// Callback choosing one of the pixel formats offered by a decoder
+pub type GetFormatFn = dyn FnMut(&[AVPixelFormat]) -> AVPixelFormat + Send;
+
+/// Owns a `get_format` callback installed with [`set_get_format`].
+#[must_use = "the callback is freed when the guard is dropped"]
+pub struct GetFormatCallback {
+    _callback: Box<Box<GetFormatFn>>,
+}
+
+/// Install a Rust callback as the `get_format` callback of a decoder.
+///
+/// The callback is stored in the `opaque` field of the context.
+///
+/// # Safety
+/// `ctx` must point to a valid codec context, and the returned guard must
+/// outlive every use of the context which may call `get_format` (opening it,
+/// sending packets, receiving frames). The callback must not panic.
+pub unsafe fn set_get_format(
+    ctx: *mut AVCodecContext,
+    callback: impl FnMut(&[AVPixelFormat]) -> AVPixelFormat + Send + 'static,
+) -> GetFormatCallback {
+    let mut callback: Box<Box<GetFormatFn>> = Box::new(Box::new(callback));
+    (*ctx).opaque = (&mut *callback as *mut Box<GetFormatFn>).cast();
+    (*ctx).get_format = Some(get_format_trampoline);
+    GetFormatCallback {
+        _callback: callback,
+    }
+}
+
+/// Install a `get_format` callback which selects `AV_PIX_FMT_DRM_PRIME` when
+/// the decoder offers it, so decoded frames stay in DRM buffers. Otherwise the
+/// first software format is chosen.
+///
+/// # Safety
+/// See [`set_get_format`].
+pub unsafe fn set_get_format_drm_prime(ctx: *mut AVCodecContext) -> GetFormatCallback {
+    set_get_format(ctx, |pix_fmts| {
+        if pix_fmts.contains(&ffi::AV_PIX_FMT_DRM_PRIME) {
+            return ffi::AV_PIX_FMT_DRM_PRIME;
+        }
+        pix_fmts
+            .iter()
+            .copied()
+            .find(|&pix_fmt| !pixdesc::is_hwaccel(pix_fmt))
+            .unwrap_or(ffi::AV_PIX_FMT_NONE)
+    })
+}
+
+unsafe extern "C" fn get_format_trampoline(
+    ctx: *mut AVCodecContext,
+    pix_fmts: *const AVPixelFormat,
+) -> AVPixelFormat {
+    let callback = &mut *((*ctx).opaque as *mut Box<GetFormatFn>);
+    callback(pix_fmt_list(pix_fmts))
+}
+
+/// Slice of a pixel format array terminated by `AV_PIX_FMT_NONE`.
+unsafe fn pix_fmt_list<'a>(pix_fmts: *const AVPixelFormat) -> &'a [AVPixelFormat] {
+    if pix_fmts.is_null() {
+        return &[];
+    }
+    let mut len = 0;
+    while *pix_fmts.add(len) != ffi::AV_PIX_FMT_NONE {
+        len += 1;
+    }
+    slice::from_raw_parts(pix_fmts, len)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::frame::Frame;
+    use crate::packet;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_supported_pix_fmts() {
+        let Some(codec) = (unsafe { ffi::avcodec_find_encoder(ffi::AV_CODEC_ID_MJPEG).as_ref() })
+        else {
+            // The encoder is not enabled in this FFmpeg build
+            return;
+        };
+        let pix_fmts = supported_pix_fmts(codec);
+        assert!(pix_fmts.contains(&ffi::AV_PIX_FMT_YUVJ420P));
+        assert!(!pix_fmts.contains(&ffi::AV_PIX_FMT_NONE));
+    }
+
+    #[test]
+    fn test_is_hardware() {
+        if let Some(codec) = unsafe { ffi::avcodec_find_encoder(ffi::AV_CODEC_ID_MJPEG).as_ref() } {
+            assert!(!is_hardware(codec));
+        }
+        let Some(codec) =
+            (unsafe { ffi::avcodec_find_decoder_by_name(c"h264_rkmpp".as_ptr()).as_ref() })
+        else {
+            // FFmpeg is built without the rkmpp codecs
+            return;
+        };
+        assert!(is_hardware(codec));
+        assert_ne!(capabilities(codec) & ffi::AV_CODEC_CAP_HARDWARE, 0);
+    }
+
+    #[test]
+    fn test_parameters() {
+        let codec = unsafe { ffi::avcodec_find_encoder(ffi::AV_CODEC_ID_MJPEG) };
+        if codec.is_null() {
+            // The encoder is not enabled in this FFmpeg build
+            return;
+        }
+        unsafe {
+            let mut ctx = ffi::avcodec_alloc_context3(codec);
+            (*ctx).width = 64;
+            (*ctx).height = 48;
+            (*ctx).pix_fmt = ffi::AV_PIX_FMT_YUVJ420P;
+            (*ctx).time_base = ffi::AVRational { num: 1, den: 25 };
+            assert_eq!(ffi::avcodec_open2(ctx, codec, ptr::null_mut()), 0);
+
+            let mut par = ffi::avcodec_parameters_alloc();
+            parameters_from_context(par, ctx).unwrap();
+            assert_eq!((*par).codec_id, ffi::AV_CODEC_ID_MJPEG);
+            assert_eq!(((*par).width, (*par).height), (64, 48));
+            assert_eq!((*par).format, ffi::AV_PIX_FMT_YUVJ420P);
+
+            let mut copy_ctx = ffi::avcodec_alloc_context3(ptr::null());
+            parameters_to_context(copy_ctx, par).unwrap();
+            assert_eq!((*copy_ctx).codec_id, ffi::AV_CODEC_ID_MJPEG);
+            assert_eq!(((*copy_ctx).width, (*copy_ctx).height), (64, 48));
+
+            ffi::avcodec_free_context(&mut copy_ctx);
+            ffi::avcodec_parameters_free(&mut par);
+            ffi::avcodec_free_context(&mut ctx);
+        }
+    }
+
+    #[test]
+    fn test_retry_busy() {
+        let mut results = vec![0, ffi::AVERROR(ffi::EBUSY)];
+        let ret = retry_busy(3, Duration::from_millis(1), || results.pop().unwrap());
+        assert_eq!(ret, Ok(()));
+        assert!(results.is_empty());
+
+        let mut num_calls = 0;
+        let ret = retry_busy(3, Duration::from_millis(1), || {
+            num_calls += 1;
+            ffi::AVERROR(ffi::EAGAIN)
+        });
+        assert!(ret.unwrap_err().contains("gave up after 3 attempts"));
+        assert_eq!(num_calls, 3);
+
+        let mut num_calls = 0;
+        let ret = retry_busy(3, Duration::from_millis(1), || {
+            num_calls += 1;
+            ffi::AVERROR(ffi::EINVAL)
+        });
+        assert_eq!(ret, Err(ffi::av_err2str(ffi::AVERROR(ffi::EINVAL))));
+        assert_eq!(num_calls, 1);
+    }
+
+    #[test]
+    fn test_enable_export_mvs() {
+        unsafe {
+            let mut ctx = ffi::avcodec_alloc_context3(ptr::null());
+            enable_export_mvs(&mut *ctx);
+            assert_ne!(
+                (*ctx).export_side_data & ffi::AV_CODEC_EXPORT_DATA_MVS as i32,
+                0
+            );
+            ffi::avcodec_free_context(&mut ctx);
+        }
+    }
+
+    #[test]
+    fn test_get_format() {
+        let (Some(encoder), Some(decoder)) = (unsafe {
+            (
+                ffi::avcodec_find_encoder(ffi::AV_CODEC_ID_MJPEG).as_ref(),
+                ffi::avcodec_find_decoder(ffi::AV_CODEC_ID_MJPEG).as_ref(),
+            )
+        }) else {
+            // The codecs are not enabled in this FFmpeg build
+            return;
+        };
+
+        // The decoder offers the formats of a JPEG image once it parses its header
+        let mut encoder_ctx = CodecContext::new(encoder).unwrap();
+        encoder_ctx.width = 64;
+        encoder_ctx.height = 48;
+        encoder_ctx.pix_fmt = ffi::AV_PIX_FMT_YUVJ420P;
+        encoder_ctx.time_base = ffi::AVRational { num: 1, den: 25 };
+        encoder_ctx.open().unwrap();
+        let mut frame = Frame::new().unwrap();
+        frame.format = ffi::AV_PIX_FMT_YUVJ420P;
+        frame.width = 64;
+        frame.height = 48;
+        frame.pts = 0;
+        assert_eq!(
+            unsafe { ffi::av_frame_get_buffer(frame.as_mut_ptr(), 0) },
+            0
+        );
+        for plane in 0..3 {
+            frame.plane_mut(plane).unwrap().fill(128);
+        }
+        encoder_ctx.send_frame(Some(&frame)).unwrap();
+        encoder_ctx.send_frame(None).unwrap();
+        let ReceivedPacket::Packet(mut packet) = encoder_ctx.receive_packet().unwrap() else {
+            panic!("No packet was encoded");
+        };
+
+        let mut decoder_ctx = CodecContext::new(decoder).unwrap();
+        let offered = Arc::new(Mutex::new(vec![]));
+        let callback_offered = offered.clone();
+        let _callback = unsafe {
+            set_get_format(decoder_ctx.as_mut_ptr(), move |pix_fmts| {
+                callback_offered.lock().unwrap().extend_from_slice(pix_fmts);
+                // Hardware formats come first, the software one is the last
+                *pix_fmts.last().unwrap()
+            })
+        };
+        decoder_ctx.open().unwrap();
+        let mut decoded = Frame::new().unwrap();
+        unsafe {
+            assert_eq!(
+                ffi::avcodec_send_packet(decoder_ctx.as_mut_ptr(), packet.as_mut_ptr()),
+                0
+            );
+            assert_eq!(
+                ffi::avcodec_receive_frame(decoder_ctx.as_mut_ptr(), decoded.as_mut_ptr()),
+                0
+            );
+        }
+
+        let offered = offered.lock().unwrap();
+        assert!(!offered.is_empty(), "the decoder didn't call get_format");
+        assert_eq!(decoded.format, *offered.last().unwrap());
+        assert_eq!((decoded.width, decoded.height), (64, 48));
+    }
+
+    #[test]
+    fn test_get_format_drm_prime() {
+        unsafe {
+            let mut ctx = ffi::avcodec_alloc_context3(ptr::null());
+            let _callback = set_get_format_drm_prime(ctx);
+            let get_format = (*ctx).get_format.unwrap();
+
+            let pix_fmts = [
+                ffi::AV_PIX_FMT_NV12,
+                ffi::AV_PIX_FMT_DRM_PRIME,
+                ffi::AV_PIX_FMT_NONE,
+            ];
+            assert_eq!(
+                get_format(ctx, pix_fmts.as_ptr()),
+                ffi::AV_PIX_FMT_DRM_PRIME
+            );
+
+            let pix_fmts = [ffi::AV_PIX_FMT_NV12, ffi::AV_PIX_FMT_NONE];
+            assert_eq!(get_format(ctx, pix_fmts.as_ptr()), ffi::AV_PIX_FMT_NV12);
+
+            ffi::avcodec_free_context(&mut ctx);
+        }
+    }
+
+    #[test]
+    fn test_extradata() {
+        let mut ctx_ptr = unsafe { ffi::avcodec_alloc_context3(ptr::null()) };
+        let ctx = unsafe { &mut *ctx_ptr };
+        assert!(extradata(ctx).is_empty());
+
+        // avcC header of a Constrained Baseline level 3.0 stream
+        let avcc = [0x01, 0x42, 0xC0, 0x1E, 0xFF, 0xE0, 0x00];
+        set_extradata(ctx, &avcc).unwrap();
+        assert_eq!(extradata(ctx), avcc);
+        assert_eq!(ctx.extradata_size, avcc.len() as i32);
+        let padding = unsafe {
+            slice::from_raw_parts(
+                ctx.extradata.add(avcc.len()),
+                ffi::AV_INPUT_BUFFER_PADDING_SIZE as usize,
+            )
+        };
+        assert!(padding.iter().all(|&b| b == 0));
+
+        // Replacing frees the previous data
+        set_extradata(ctx, &avcc[..4]).unwrap();
+        assert_eq!(extradata(ctx), &avcc[..4]);
+        set_extradata(ctx, &[]).unwrap();
+        assert!(extradata(ctx).is_empty());
+
+        // The extradata is freed with the context
+        unsafe { ffi::avcodec_free_context(&mut ctx_ptr) };
+    }
+
+    #[test]
+    fn test_codec_context_encode() {
+        let Some(codec) = (unsafe { ffi::avcodec_find_encoder(ffi::AV_CODEC_ID_MJPEG).as_ref() })
+        else {
+            // The encoder is not enabled in this FFmpeg build
+            return;
+        };
+        let mut ctx = CodecContext::new(codec).unwrap();
+        ctx.width = 64;
+        ctx.height = 48;
+        ctx.pix_fmt = ffi::AV_PIX_FMT_YUVJ420P;
+        ctx.time_base = ffi::AVRational { num: 1, den: 25 };
+        ctx.open().unwrap();
+
+        let mut frame = Frame::new().unwrap();
+        frame.format = ffi::AV_PIX_FMT_YUVJ420P;
+        frame.width = 64;
+        frame.height = 48;
+        frame.pts = 0;
+        assert_eq!(
+            unsafe { ffi::av_frame_get_buffer(frame.as_mut_ptr(), 0) },
+            0
+        );
+        for plane in 0..3 {
+            frame.plane_mut(plane).unwrap().fill(128);
+        }
+
+        assert!(matches!(ctx.receive_packet(), Ok(ReceivedPacket::Again)));
+        ctx.send_frame(Some(&frame)).unwrap();
+        ctx.send_frame(None).unwrap();
+        let ReceivedPacket::Packet(packet) = ctx.receive_packet().unwrap() else {
+            panic!("No packet was encoded");
+        };
+        assert!(packet.size > 0);
+        assert!(packet::is_keyframe(&packet));
+        assert!(matches!(ctx.receive_packet(), Ok(ReceivedPacket::Eof)));
+    }
+}
@@ -0,0 +1,110 @@
+use crate::ffi::{self, AVCodecContext};
+use core::ffi::c_int;
+use core::ops::{BitOr, BitOrAssign};
+
+/// A combination of the `AV_CODEC_FLAG_*` flags of a codec context.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CodecFlags(c_int);
+
+impl CodecFlags {
+    pub const UNALIGNED: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_UNALIGNED);
+    /// Use a fixed quality scale
+    pub const QSCALE: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_QSCALE);
+    pub const FOUR_MV: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_4MV);
+    /// Output even the frames which may be corrupted
+    pub const OUTPUT_CORRUPT: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_OUTPUT_CORRUPT);
+    pub const QPEL: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_QPEL);
+    /// Request the reconstructed frames from an encoder
+    #[cfg(any(feature = "ffmpeg6", feature = "ffmpeg7"))]
+    pub const RECON_FRAME: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_RECON_FRAME);
+    /// Propagate the `opaque` fields of frames to packets and vice versa
+    #[cfg(any(feature = "ffmpeg6", feature = "ffmpeg7"))]
+    pub const COPY_OPAQUE: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_COPY_OPAQUE);
+    /// Use the frame durations instead of the frame rate for packet durations
+    #[cfg(any(feature = "ffmpeg6", feature = "ffmpeg7"))]
+    pub const FRAME_DURATION: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_FRAME_DURATION);
+    pub const PASS1: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_PASS1);
+    pub const PASS2: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_PASS2);
+    pub const LOOP_FILTER: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_LOOP_FILTER);
+    /// Only decode or encode grayscale
+    pub const GRAY: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_GRAY);
+    /// Compute the PSNR of the encoded frames
+    pub const PSNR: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_PSNR);
+    pub const INTERLACED_DCT: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_INTERLACED_DCT);
+    /// Force low delay, e.g. disable B-frames
+    pub const LOW_DELAY: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_LOW_DELAY);
+    /// Put the stream headers into `extradata` instead of every keyframe,
+    /// required by containers like mp4
+    pub const GLOBAL_HEADER: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_GLOBAL_HEADER);
+    /// Only use bit-exact algorithms, e.g. for tests
+    pub const BITEXACT: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_BITEXACT);
+    pub const AC_PRED: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_AC_PRED);
+    pub const INTERLACED_ME: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_INTERLACED_ME);
+    pub const CLOSED_GOP: Self = Self::from_ffi(ffi::AV_CODEC_FLAG_CLOSED_GOP);
+
+    const fn from_ffi(flag: u32) -> Self {
+        Self(flag as c_int)
+    }
+
+    pub const fn empty() -> Self {
+        Self(0)
+    }
+
+    /// Value for the `flags` field of a codec context
+    pub const fn bits(self) -> c_int {
+        self.0
+    }
+
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    /// Flags currently set on a codec context.
+    pub fn of(ctx: &AVCodecContext) -> Self {
+        Self(ctx.flags)
+    }
+
+    /// Set the flags on a codec context, keeping the ones already set.
+    pub fn set_on(self, ctx: &mut AVCodecContext) {
+        ctx.flags |= self.0;
+    }
+}
+
+impl BitOr for CodecFlags {
+    type Output = Self;
+
+    fn bitor(self, rhs: Self) -> Self {
+        Self(self.0 | rhs.0)
+    }
+}
+
+impl BitOrAssign for CodecFlags {
+    fn bitor_assign(&mut self, rhs: Self) {
+        self.0 |= rhs.0;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn test_set_on() {
+        let flags = CodecFlags::LOW_DELAY | CodecFlags::GLOBAL_HEADER;
+        assert!(flags.contains(CodecFlags::LOW_DELAY));
+        assert!(!flags.contains(CodecFlags::QSCALE));
+        assert_eq!(
+            flags.bits(),
+            (ffi::AV_CODEC_FLAG_LOW_DELAY | ffi::AV_CODEC_FLAG_GLOBAL_HEADER) as c_int
+        );
+
+        unsafe {
+            let mut ctx = ffi::avcodec_alloc_context3(ptr::null());
+            CodecFlags::QSCALE.set_on(&mut *ctx);
+            flags.set_on(&mut *ctx);
+            assert_eq!(CodecFlags::of(&*ctx), flags | CodecFlags::QSCALE);
+            ffi::avcodec_free_context(&mut ctx);
+        }
+    }
+}
@@ -0,0 +1,68 @@
+use crate::ffi::{
+    self, AVColorPrimaries, AVColorRange, AVColorSpace, AVColorTransferCharacteristic,
+};
+use alloc::string::String;
+use core::ffi::{c_char, CStr};
+
+/// Name of a color space (e.g. `bt709`), `None` if the value is unknown.
+pub fn colorspace_name(space: AVColorSpace) -> Option<String> {
+    unsafe { static_name(ffi::av_color_space_name(space)) }
+}
+
+/// Name of a color range: `tv` for limited and `pc` for full range.
+pub fn color_range_name(range: AVColorRange) -> Option<String> {
+    unsafe { static_name(ffi::av_color_range_name(range)) }
+}
+
+/// Name of color primaries (e.g. `bt709`), `None` if the value is unknown.
+pub fn color_primaries_name(primaries: AVColorPrimaries) -> Option<String> {
+    unsafe { static_name(ffi::av_color_primaries_name(primaries)) }
+}
+
+/// Name of a transfer characteristic (e.g. `smpte2084`), `None` if the value
+/// is unknown.
+pub fn color_trc_name(trc: AVColorTransferCharacteristic) -> Option<String> {
+    unsafe { static_name(ffi::av_color_transfer_name(trc)) }
+}
+
+unsafe fn static_name(name: *const c_char) -> Option<String> {
+    if name.is_null() {
+        return None;
+    }
+    Some(CStr::from_ptr(name).to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_bt709_names() {
+        assert_eq!(
+            colorspace_name(ffi::AVCOL_SPC_BT709).as_deref(),
+            Some("bt709")
+        );
+        assert_eq!(
+            color_primaries_name(ffi::AVCOL_PRI_BT709).as_deref(),
+            Some("bt709")
+        );
+        assert_eq!(
+            color_trc_name(ffi::AVCOL_TRC_BT709).as_deref(),
+            Some("bt709")
+        );
+        assert_eq!(colorspace_name(ffi::AVCOL_SPC_NB), None);
+    }
+
+    #[test]
+    fn test_color_range_names() {
+        assert_eq!(
+            color_range_name(ffi::AVCOL_RANGE_JPEG).as_deref(),
+            Some("pc")
+        );
+        assert_eq!(
+            color_range_name(ffi::AVCOL_RANGE_MPEG).as_deref(),
+            Some("tv")
+        );
+        assert_eq!(color_range_name(ffi::AVCOL_RANGE_NB), None);
+    }
+}
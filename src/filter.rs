@@ -0,0 +1,268 @@
+use crate::buffer::Buffer;
+use crate::ffi::{
+    self, AVFilterContext, AVFilterGraph, AVFilterInOut, AVFrame, AVPixelFormat, AVRational,
+};
+use crate::frame::Frame;
+use std::ffi::{c_int, CStr, CString};
+use std::ptr::{self, NonNull};
+use std::slice;
+
+/// A configured video filter graph fed through a `buffer` source and drained
+/// through a `buffersink`, e.g. to scale frames with `scale_rkrga`.
+pub struct FilterGraph {
+    graph: NonNull<AVFilterGraph>,
+    src: *mut AVFilterContext,
+    sink: *mut AVFilterContext,
+}
+
+impl FilterGraph {
+    /// Parse and configure the graph described by `filters` (e.g.
+    /// `scale=w=640:h=360`) for input frames of the given size, pixel format
+    /// and time base.
+    ///
+    /// `hw_device` is attached to all the filters of the graph, it is needed
+    /// by the filters creating hardware frames, e.g. `hwupload`.
+    pub fn video(
+        filters: &str,
+        width: i32,
+        height: i32,
+        pix_fmt: AVPixelFormat,
+        time_base: AVRational,
+        hw_device: Option<&Buffer>,
+    ) -> Result<Self, String> {
+        let filters = CString::new(filters).map_err(|e| e.to_string())?;
+        let graph = NonNull::new(unsafe { ffi::avfilter_graph_alloc() })
+            .ok_or_else(|| ffi::av_err2str(ffi::AVERROR(ffi::ENOMEM)))?;
+        let mut filter_graph = Self {
+            graph,
+            src: ptr::null_mut(),
+            sink: ptr::null_mut(),
+        };
+        let src_args = format!(
+            "video_size={width}x{height}:pix_fmt={pix_fmt}:time_base={}/{}:pixel_aspect=1/1",
+            time_base.num, time_base.den
+        );
+        filter_graph.src = filter_graph.create_filter(c"buffer", c"in", Some(&src_args))?;
+        filter_graph.sink = filter_graph.create_filter(c"buffersink", c"out", None)?;
+        unsafe {
+            filter_graph.parse(&filters)?;
+            if let Some(hw_device) = hw_device {
+                filter_graph.set_hw_device(hw_device)?;
+            }
+            let ret = ffi::avfilter_graph_config(filter_graph.graph.as_ptr(), ptr::null_mut());
+            if ret < 0 {
+                return Err(ffi::av_err2str(ret));
+            }
+        }
+        Ok(filter_graph)
+    }
+
+    pub fn as_ptr(&self) -> *const AVFilterGraph {
+        self.graph.as_ptr()
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut AVFilterGraph {
+        self.graph.as_ptr()
+    }
+
+    /// Send a frame to the graph, `None` marks the end of the input.
+    ///
+    /// The frame is referenced, not consumed. Like `avcodec_send_frame`, this
+    /// fails with `EAGAIN` when the output must be pulled first.
+    pub fn push_frame(&mut self, frame: Option<&AVFrame>) -> Result<(), String> {
+        let ret = unsafe {
+            match frame {
+                Some(frame) => ffi::av_buffersrc_add_frame_flags(
+                    self.src,
+                    ptr::from_ref(frame).cast_mut(),
+                    ffi::AV_BUFFERSRC_FLAG_KEEP_REF as c_int,
+                ),
+                None => ffi::av_buffersrc_add_frame_flags(self.src, ptr::null_mut(), 0),
+            }
+        };
+        if ret < 0 {
+            return Err(ffi::av_err2str(ret));
+        }
+        Ok(())
+    }
+
+    /// Receive a filtered frame, like `avcodec_receive_frame`.
+    pub fn pull_frame(&mut self) -> Result<PulledFrame, String> {
+        let mut frame = Frame::new().ok_or_else(|| ffi::av_err2str(ffi::AVERROR(ffi::ENOMEM)))?;
+        match unsafe { ffi::av_buffersink_get_frame(self.sink, frame.as_mut_ptr()) } {
+            ffi::AVERROR_EOF => Ok(PulledFrame::Eof),
+            ret if ret == ffi::AVERROR(ffi::EAGAIN) => Ok(PulledFrame::Again),
+            ret if ret < 0 => Err(ffi::av_err2str(ret)),
+            _ => Ok(PulledFrame::Frame(frame)),
+        }
+    }
+
+    fn create_filter(
+        &mut self,
+        filter_name: &CStr,
+        name: &CStr,
+        args: Option<&str>,
+    ) -> Result<*mut AVFilterContext, String> {
+        let filter = unsafe { ffi::avfilter_get_by_name(filter_name.as_ptr()) };
+        if filter.is_null() {
+            return Err(format!("Filter {filter_name:?} is not available"));
+        }
+        let args = args
+            .map(CString::new)
+            .transpose()
+            .map_err(|e| e.to_string())?;
+        let mut ctx = ptr::null_mut();
+        let ret = unsafe {
+            ffi::avfilter_graph_create_filter(
+                &mut ctx,
+                filter,
+                name.as_ptr(),
+                args.as_ref().map_or(ptr::null(), |args| args.as_ptr()),
+                ptr::null_mut(),
+                self.graph.as_ptr(),
+            )
+        };
+        if ret < 0 {
+            return Err(ffi::av_err2str(ret));
+        }
+        Ok(ctx)
+    }
+
+    /// Link the parsed filters between the source and the sink.
+    unsafe fn parse(&mut self, filters: &CStr) -> Result<(), String> {
+        // The open output of the source is the input of the parsed filters
+        // and vice versa
+        let mut outputs = inout(c"in", self.src)?;
+        let mut inputs = match inout(c"out", self.sink) {
+            Ok(inputs) => inputs,
+            Err(e) => {
+                ffi::avfilter_inout_free(&mut outputs);
+                return Err(e);
+            }
+        };
+        let ret = ffi::avfilter_graph_parse_ptr(
+            self.graph.as_ptr(),
+            filters.as_ptr(),
+            &mut inputs,
+            &mut outputs,
+            ptr::null_mut(),
+        );
+        ffi::avfilter_inout_free(&mut inputs);
+        ffi::avfilter_inout_free(&mut outputs);
+        if ret < 0 {
+            return Err(ffi::av_err2str(ret));
+        }
+        Ok(())
+    }
+
+    unsafe fn set_hw_device(&mut self, hw_device: &Buffer) -> Result<(), String> {
+        let graph = self.graph.as_ref();
+        for &filter in slice::from_raw_parts(graph.filters, graph.nb_filters as usize) {
+            let device = ffi::av_buffer_ref(hw_device.as_ptr());
+            if device.is_null() {
+                return Err(ffi::av_err2str(ffi::AVERROR(ffi::ENOMEM)));
+            }
+            (*filter).hw_device_ctx = device;
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of [`FilterGraph::pull_frame`].
+#[derive(Debug)]
+pub enum PulledFrame {
+    Frame(Frame),
+    /// More frames must be pushed first (`EAGAIN`)
+    Again,
+    /// The input has ended and all the frames were pulled (`EOF`)
+    Eof,
+}
+
+impl Drop for FilterGraph {
+    fn drop(&mut self) {
+        // The filters are freed with the graph
+        let mut graph = self.graph.as_ptr();
+        unsafe { ffi::avfilter_graph_free(&mut graph) };
+    }
+}
+
+unsafe fn inout(
+    name: &CStr,
+    filter_ctx: *mut AVFilterContext,
+) -> Result<*mut AVFilterInOut, String> {
+    let mut inout = ffi::avfilter_inout_alloc();
+    if inout.is_null() {
+        return Err(ffi::av_err2str(ffi::AVERROR(ffi::ENOMEM)));
+    }
+    (*inout).name = ffi::av_strdup(name.as_ptr());
+    if (*inout).name.is_null() {
+        ffi::avfilter_inout_free(&mut inout);
+        return Err(ffi::av_err2str(ffi::AVERROR(ffi::ENOMEM)));
+    }
+    (*inout).filter_ctx = filter_ctx;
+    (*inout).pad_idx = 0;
+    (*inout).next = ptr::null_mut();
+    Ok(inout)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_scale_graph() {
+        if unsafe { ffi::avfilter_get_by_name(c"scale".as_ptr()) }.is_null() {
+            // The filter is not enabled in this FFmpeg build
+            return;
+        }
+        let time_base = AVRational { num: 1, den: 25 };
+        let mut graph = FilterGraph::video(
+            "scale=w=32:h=24",
+            64,
+            48,
+            ffi::AV_PIX_FMT_YUV420P,
+            time_base,
+            None,
+        )
+        .unwrap();
+
+        let mut frame = Frame::new().unwrap();
+        frame.format = ffi::AV_PIX_FMT_YUV420P;
+        frame.width = 64;
+        frame.height = 48;
+        frame.pts = 0;
+        assert_eq!(
+            unsafe { ffi::av_frame_get_buffer(frame.as_mut_ptr(), 0) },
+            0
+        );
+        frame.plane_mut(0).unwrap().fill(16);
+        frame.plane_mut(1).unwrap().fill(128);
+        frame.plane_mut(2).unwrap().fill(128);
+
+        assert!(matches!(graph.pull_frame(), Ok(PulledFrame::Again)));
+        graph.push_frame(Some(&frame)).unwrap();
+        graph.push_frame(None).unwrap();
+
+        let PulledFrame::Frame(scaled) = graph.pull_frame().unwrap() else {
+            panic!("No frame was filtered");
+        };
+        assert_eq!((scaled.width, scaled.height), (32, 24));
+        assert_eq!(scaled.format, ffi::AV_PIX_FMT_YUV420P);
+        assert_eq!(scaled.pts, 0);
+        assert!(matches!(graph.pull_frame(), Ok(PulledFrame::Eof)));
+    }
+
+    #[test]
+    fn test_invalid_graph() {
+        let time_base = AVRational { num: 1, den: 25 };
+        let graph = FilterGraph::video(
+            "no_such_filter",
+            64,
+            48,
+            ffi::AV_PIX_FMT_YUV420P,
+            time_base,
+            None,
+        );
+        assert!(graph.is_err());
+    }
+}
@@ -0,0 +1,188 @@
+use crate::ffi::{self, AVFormatContext, AVOutputFormat, AVPacket};
+use std::ffi::{CStr, CString};
+use std::ptr;
+
+/// Open an input file (or URL) and read its header.
+///
+/// The context must be closed with `avformat_close_input`.
+pub fn open_input(path: &str) -> Result<*mut AVFormatContext, String> {
+    let path = CString::new(path).map_err(|e| e.to_string())?;
+    let mut ctx = ptr::null_mut();
+    let ret =
+        unsafe { ffi::avformat_open_input(&mut ctx, path.as_ptr(), ptr::null(), ptr::null_mut()) };
+    if ret < 0 {
+        return Err(ffi::av_err2str(ret));
+    }
+    Ok(ctx)
+}
+
+/// Read packets of the input to fill the stream parameters missing from the
+/// header (e.g. of raw H.264 streams).
+///
+/// # Safety
+/// `ctx` must point to a format context opened with [`open_input`].
+pub unsafe fn find_stream_info(ctx: *mut AVFormatContext) -> Result<(), String> {
+    let ret = ffi::avformat_find_stream_info(ctx, ptr::null_mut());
+    if ret < 0 {
+        return Err(ffi::av_err2str(ret));
+    }
+    Ok(())
+}
+
+/// Index of the most suitable video stream of the input.
+///
+/// # Safety
+/// `ctx` must point to a format context opened with [`open_input`].
+pub unsafe fn best_video_stream(ctx: *mut AVFormatContext) -> Result<i32, String> {
+    let ret = ffi::av_find_best_stream(ctx, ffi::AVMEDIA_TYPE_VIDEO, -1, -1, ptr::null_mut(), 0);
+    if ret < 0 {
+        return Err(ffi::av_err2str(ret));
+    }
+    Ok(ret)
+}
+
+/// Read the next packet of any stream into `packet`, returns `false` at the
+/// end of the input.
+///
+/// # Safety
+/// `ctx` must point to a format context opened with [`open_input`] and
+/// `packet` to an allocated packet, its previous data is unreferenced.
+pub unsafe fn read_packet(
+    ctx: *mut AVFormatContext,
+    packet: *mut AVPacket,
+) -> Result<bool, String> {
+    ffi::av_packet_unref(packet);
+    match ffi::av_read_frame(ctx, packet) {
+        ffi::AVERROR_EOF => Ok(false),
+        ret if ret < 0 => Err(ffi::av_err2str(ret)),
+        _ => Ok(true),
+    }
+}
+
+/// Muxer matching a format name (e.g. `mp4`), the extension of a file name
+/// or a MIME type, `None` if no enabled muxer matches.
+///
+/// Unless the muxer has the `AVFMT_NOFILE` flag, the `pb` of the output
+/// context must be opened with [`avio::open_write`] before
+/// `avformat_write_header` and closed with [`avio::close`] after
+/// `av_write_trailer`. Close it also when muxing fails, the file keeps the
+/// data written so far.
+///
+/// [`avio::open_write`]: crate::avio::open_write
+/// [`avio::close`]: crate::avio::close
+pub fn guess_format(
+    short_name: Option<&str>,
+    filename: Option<&str>,
+    mime_type: Option<&str>,
+) -> Option<&'static AVOutputFormat> {
+    let to_cstring = |s: Option<&str>| s.map(CString::new).transpose().ok();
+    let short_name = to_cstring(short_name)?;
+    let filename = to_cstring(filename)?;
+    let mime_type = to_cstring(mime_type)?;
+    let as_ptr = |s: &Option<CString>| s.as_ref().map_or(ptr::null(), |s| s.as_ptr());
+    unsafe {
+        ffi::av_guess_format(as_ptr(&short_name), as_ptr(&filename), as_ptr(&mime_type)).as_ref()
+    }
+}
+
+/// Short name of a muxer, e.g. `mp4`.
+pub fn output_format_name(format: &AVOutputFormat) -> String {
+    unsafe { CStr::from_ptr(format.name) }
+        .to_string_lossy()
+        .into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::avio;
+    use crate::packet::Packet;
+    use std::ffi::c_int;
+
+    /// Writes an mp4 file with a single packet of a video stream
+    unsafe fn write_mp4(path: &CString, data: &[u8]) -> bool {
+        let mut ctx = ptr::null_mut();
+        if ffi::avformat_alloc_output_context2(
+            &mut ctx,
+            ptr::null(),
+            c"mp4".as_ptr(),
+            path.as_ptr(),
+        ) < 0
+        {
+            return false;
+        }
+        let stream = ffi::avformat_new_stream(ctx, ptr::null());
+        let par = &mut *(*stream).codecpar;
+        par.codec_type = ffi::AVMEDIA_TYPE_VIDEO;
+        par.codec_id = ffi::AV_CODEC_ID_MJPEG;
+        par.width = 64;
+        par.height = 48;
+        (*stream).time_base = ffi::AVRational { num: 1, den: 25 };
+        (*ctx).pb = avio::open_write(path.to_str().unwrap()).unwrap();
+        assert_eq!(ffi::avformat_write_header(ctx, ptr::null_mut()), 0);
+
+        let mut packet = Packet::new().unwrap();
+        assert_eq!(
+            ffi::av_new_packet(packet.as_mut_ptr(), data.len() as c_int),
+            0
+        );
+        std::slice::from_raw_parts_mut(packet.data, data.len()).copy_from_slice(data);
+        packet.pts = 0;
+        packet.dts = 0;
+        packet.flags |= crate::packet::AV_PKT_FLAG_KEY;
+        assert_eq!(ffi::av_write_frame(ctx, packet.as_mut_ptr()), 0);
+
+        assert_eq!(ffi::av_write_trailer(ctx), 0);
+        avio::close(&mut (*ctx).pb).unwrap();
+        ffi::avformat_free_context(ctx);
+        true
+    }
+
+    #[test]
+    fn test_read_mp4() {
+        let path =
+            std::env::temp_dir().join(format!("rusty_ffmpeg_format_{}.mp4", std::process::id()));
+        let path = path.to_str().unwrap();
+        let data = b"not really a jpeg";
+        if !unsafe { write_mp4(&CString::new(path).unwrap(), data) } {
+            // The muxer is not enabled in this FFmpeg build
+            return;
+        }
+
+        let Ok(mut ctx) = open_input(path) else {
+            // The demuxer is not enabled in this FFmpeg build
+            std::fs::remove_file(path).unwrap();
+            return;
+        };
+        let mut packet = Packet::new().unwrap();
+        unsafe {
+            find_stream_info(ctx).unwrap();
+            assert_eq!(best_video_stream(ctx), Ok(0));
+            assert!(read_packet(ctx, packet.as_mut_ptr()).unwrap());
+            assert_eq!(packet.stream_index, 0);
+            assert_eq!(
+                std::slice::from_raw_parts(packet.data, packet.size as usize),
+                data
+            );
+            assert!(!read_packet(ctx, packet.as_mut_ptr()).unwrap());
+            ffi::avformat_close_input(&mut ctx);
+        }
+        std::fs::remove_file(path).unwrap();
+
+        assert!(open_input(path).is_err());
+    }
+
+    #[test]
+    fn test_guess_format() {
+        let mp4_enabled =
+            !unsafe { ffi::av_guess_format(c"mp4".as_ptr(), ptr::null(), ptr::null()) }.is_null();
+        let format = guess_format(None, Some("out.mp4"), None);
+        if mp4_enabled {
+            assert_eq!(format.map(output_format_name).as_deref(), Some("mp4"));
+        } else {
+            assert!(format.is_none());
+        }
+        assert!(guess_format(None, Some("out.unknown_extension"), None).is_none());
+        assert!(guess_format(Some("no_such_muxer"), None, None).is_none());
+    }
+}
@@ -0,0 +1,536 @@
+use crate::ffi::{
+    self, AVDynamicHDRPlus, AVFrame, AVFrameSideDataType, AVMasteringDisplayMetadata,
+    AVMotionVector,
+};
+use crate::pixdesc;
+use std::ops::{Deref, DerefMut};
+use std::ptr::{self, NonNull};
+
+/// Allocate an empty frame, `None` on allocation failure.
+///
+/// The frame must be freed with `av_frame_free`, see [`Frame`] for an owned
+/// alternative.
+pub fn alloc_frame() -> Option<*mut AVFrame> {
+    let frame = unsafe { ffi::av_frame_alloc() };
+    (!frame.is_null()).then_some(frame)
+}
+
+/// An owned `AVFrame`, freed with `av_frame_free` when dropped.
+#[derive(Debug)]
+pub struct Frame {
+    ptr: NonNull<AVFrame>,
+}
+
+impl Frame {
+    /// Allocate an empty frame, `None` on allocation failure.
+    pub fn new() -> Option<Self> {
+        unsafe { Self::from_raw(alloc_frame()?) }
+    }
+
+    /// Take ownership of a frame, `None` if `ptr` is null.
+    ///
+    /// # Safety
+    /// `ptr` must be a frame allocated with `av_frame_alloc` which is not
+    /// freed elsewhere.
+    pub unsafe fn from_raw(ptr: *mut AVFrame) -> Option<Self> {
+        NonNull::new(ptr).map(|ptr| Self { ptr })
+    }
+
+    /// Release the ownership of the frame without freeing it.
+    pub fn into_raw(self) -> *mut AVFrame {
+        let ptr = self.ptr.as_ptr();
+        std::mem::forget(self);
+        ptr
+    }
+
+    pub fn as_ptr(&self) -> *const AVFrame {
+        self.ptr.as_ptr()
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut AVFrame {
+        self.ptr.as_ptr()
+    }
+
+    /// Create a new frame referencing the same buffers with `av_frame_ref`,
+    /// the data is not copied.
+    ///
+    /// Both frames share the data, so [`AVFrame::plane_mut`] returns `None`
+    /// for either of them until the other one is dropped or the frame is made
+    /// writable (see [`make_writable`]).
+    pub fn clone_ref(&self) -> Result<Self, String> {
+        let mut frame = Self::new().ok_or_else(|| ffi::av_err2str(ffi::AVERROR(ffi::ENOMEM)))?;
+        let ret = unsafe { ffi::av_frame_ref(frame.as_mut_ptr(), self.as_ptr()) };
+        if ret < 0 {
+            return Err(ffi::av_err2str(ret));
+        }
+        Ok(frame)
+    }
+
+    /// Make the frame sendable to another thread, e.g. to the next stage of
+    /// a pipeline. Returns the frame back if it is a hardware frame.
+    pub fn into_send(self) -> Result<SendFrame, Self> {
+        if pixdesc::is_hwaccel(self.format) {
+            return Err(self);
+        }
+        Ok(SendFrame(self))
+    }
+}
+
+/// A software frame which can be sent to another thread, see
+/// [`Frame::into_send`].
+///
+/// The buffers of software frames are reference counted atomically, so a
+/// frame can be dropped on any thread. Hardware frames are excluded, as
+/// hardware devices and their buffers (e.g. DRM PRIME descriptors) may be
+/// bound to the thread which created them.
+///
+/// A frame sharing its buffers with another one (see [`Frame::clone_ref`])
+/// can be sent as well, as the shared data can only be read until the frame
+/// is made writable, which copies it.
+#[derive(Debug)]
+pub struct SendFrame(Frame);
+
+// SAFETY: see the type documentation
+unsafe impl Send for SendFrame {}
+
+impl SendFrame {
+    pub fn into_inner(self) -> Frame {
+        self.0
+    }
+}
+
+impl Deref for Frame {
+    type Target = AVFrame;
+
+    fn deref(&self) -> &AVFrame {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl DerefMut for Frame {
+    fn deref_mut(&mut self) -> &mut AVFrame {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl Drop for Frame {
+    fn drop(&mut self) {
+        let mut ptr = self.ptr.as_ptr();
+        unsafe { ffi::av_frame_free(&mut ptr) };
+    }
+}
+
+impl AVFrame {
+    /// Data of a plane of a video frame, `None` if the plane is absent.
+    ///
+    /// The slice covers `linesize` bytes for every line of the plane, taking
+    /// the vertical chroma subsampling of the pixel format into account.
+    /// Frames with negative linesizes, hardware frames and frames which are
+    /// not reference counted have no accessible planes, and neither have the
+    /// planes which don't fit into their buffers (e.g. after changing
+    /// `height`).
+    pub fn plane(&self, index: usize) -> Option<&[u8]> {
+        let len = self.plane_len(index)?;
+        Some(unsafe { std::slice::from_raw_parts(self.data[index], len) })
+    }
+
+    /// Mutable data of a plane of a video frame, see [`AVFrame::plane`].
+    ///
+    /// `None` if the frame is not writable, i.e. its buffers are shared with
+    /// another frame, see [`make_writable`].
+    pub fn plane_mut(&mut self, index: usize) -> Option<&mut [u8]> {
+        if !is_writable(self) {
+            return None;
+        }
+        let len = self.plane_len(index)?;
+        Some(unsafe { std::slice::from_raw_parts_mut(self.data[index], len) })
+    }
+
+    fn plane_len(&self, index: usize) -> Option<usize> {
+        if index >= self.data.len() || self.data[index].is_null() || self.linesize[index] < 0 {
+            return None;
+        }
+        if pixdesc::is_hwaccel(self.format) || index >= pixdesc::count_planes(self.format)? {
+            return None;
+        }
+        let height = pixdesc::plane_height(self.format, self.height, index)?;
+        let len = (self.linesize[index] as usize).checked_mul(usize::try_from(height).ok()?)?;
+        // The fields are public, so check that the plane fits into the buffer
+        // it starts in, like `av_frame_get_plane_buffer` finds it
+        let data = self.data[index] as usize;
+        let buf = self
+            .buf
+            .iter()
+            .filter_map(|buf| unsafe { buf.as_ref() })
+            .find(|buf| data >= buf.data as usize && data < buf.data as usize + buf.size)?;
+        ((data - buf.data as usize).checked_add(len)? <= buf.size).then_some(len)
+    }
+}
+
+/// Ensure that the frame data is writable, avoiding data copy if possible.
+///
+/// Do nothing if the frame is writable, allocate new buffers and copy the data
+/// if it is not.
+pub fn make_writable(frame: &mut AVFrame) -> Result<(), String> {
+    let ret = unsafe { ffi::av_frame_make_writable(frame) };
+    if ret < 0 {
+        return Err(ffi::av_err2str(ret));
+    }
+    Ok(())
+}
+
+/// Check if the frame data is writable.
+///
+/// A frame is writable if it is reference counted and its buffers are not
+/// shared with any other frame.
+pub fn is_writable(frame: &AVFrame) -> bool {
+    unsafe { ffi::av_frame_is_writable(ptr::from_ref(frame).cast_mut()) > 0 }
+}
+
+/// Number of pixels to remove from each side of a frame to get its
+/// displayable region, e.g. the padding of a frame decoded to an aligned size.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Crop {
+    pub top: usize,
+    pub bottom: usize,
+    pub left: usize,
+    pub right: usize,
+}
+
+/// Cropping of the frame which is not applied yet, see [`apply_cropping`].
+pub fn crop(frame: &AVFrame) -> Crop {
+    Crop {
+        top: frame.crop_top,
+        bottom: frame.crop_bottom,
+        left: frame.crop_left,
+        right: frame.crop_right,
+    }
+}
+
+/// Set the cropping of the frame, applied later with [`apply_cropping`].
+pub fn set_crop(frame: &mut AVFrame, crop: Crop) {
+    frame.crop_top = crop.top;
+    frame.crop_bottom = crop.bottom;
+    frame.crop_left = crop.left;
+    frame.crop_right = crop.right;
+}
+
+/// Crop the frame to its displayable region with `av_frame_apply_cropping`.
+///
+/// The data pointers and the size of the frame are adjusted and the crop
+/// fields are reset, the data is not copied. The left and top offsets are
+/// rounded to the alignment of the pixel format unless `flags` contains
+/// `AV_FRAME_CROP_UNALIGNED`, and are ignored for hardware frames. Fails with
+/// `ERANGE` and leaves the frame unchanged if the cropping exceeds its size.
+pub fn apply_cropping(frame: &mut AVFrame, flags: i32) -> Result<(), String> {
+    let ret = unsafe { ffi::av_frame_apply_cropping(frame, flags) };
+    if ret < 0 {
+        return Err(ffi::av_err2str(ret));
+    }
+    Ok(())
+}
+
+/// Presentation timestamp of a decoded frame guessed from the pts and dts of
+/// the packets, in the time base of the stream, `None` if it is unknown
+/// (`AV_NOPTS_VALUE`).
+///
+/// Unlike `pts`, it is also set for streams without presentation timestamps.
+pub fn best_effort_timestamp(frame: &AVFrame) -> Option<i64> {
+    (frame.best_effort_timestamp != ffi::AV_NOPTS_VALUE).then_some(frame.best_effort_timestamp)
+}
+
+/// Duration of the frame in the time base of the stream, `None` if it is
+/// unknown (zero).
+pub fn duration(frame: &AVFrame) -> Option<i64> {
+    (frame.duration > 0).then_some(frame.duration)
+}
+
+/// Mastering display metadata (SMPTE 2086) attached to the frame.
+pub fn mastering_display(frame: &AVFrame) -> Option<&AVMasteringDisplayMetadata> {
+    unsafe { side_data(frame, ffi::AV_FRAME_DATA_MASTERING_DISPLAY_METADATA) }
+}
+
+/// Attach mastering display metadata to the frame, replacing the existing one.
+pub fn set_mastering_display(
+    frame: &mut AVFrame,
+    metadata: &AVMasteringDisplayMetadata,
+) -> Result<(), String> {
+    unsafe {
+        ffi::av_frame_remove_side_data(frame, ffi::AV_FRAME_DATA_MASTERING_DISPLAY_METADATA);
+        let side_data = ffi::av_mastering_display_metadata_create_side_data(frame);
+        if side_data.is_null() {
+            return Err(ffi::av_err2str(ffi::AVERROR(ffi::ENOMEM)));
+        }
+        *side_data = *metadata;
+    }
+    Ok(())
+}
+
+/// HDR10+ dynamic metadata (SMPTE 2094-40) attached to the frame.
+pub fn hdr_plus(frame: &AVFrame) -> Option<&AVDynamicHDRPlus> {
+    unsafe { side_data(frame, ffi::AV_FRAME_DATA_DYNAMIC_HDR_PLUS) }
+}
+
+/// Attach HDR10+ dynamic metadata to the frame, replacing the existing one.
+pub fn set_hdr_plus(frame: &mut AVFrame, metadata: &AVDynamicHDRPlus) -> Result<(), String> {
+    unsafe {
+        ffi::av_frame_remove_side_data(frame, ffi::AV_FRAME_DATA_DYNAMIC_HDR_PLUS);
+        let side_data = ffi::av_dynamic_hdr_plus_create_side_data(frame);
+        if side_data.is_null() {
+            return Err(ffi::av_err2str(ffi::AVERROR(ffi::ENOMEM)));
+        }
+        *side_data = *metadata;
+    }
+    Ok(())
+}
+
+/// Motion vectors exported by the decoder, empty if there are none.
+///
+/// Decoders only export them when enabled with
+/// [`crate::codec::enable_export_mvs`].
+pub fn motion_vectors(frame: &AVFrame) -> &[AVMotionVector] {
+    let side_data =
+        unsafe { ffi::av_frame_get_side_data(frame, ffi::AV_FRAME_DATA_MOTION_VECTORS).as_ref() };
+    match side_data {
+        Some(side_data) if !side_data.data.is_null() => unsafe {
+            std::slice::from_raw_parts(
+                side_data.data.cast(),
+                side_data.size / std::mem::size_of::<AVMotionVector>(),
+            )
+        },
+        _ => &[],
+    }
+}
+
+/// Side data of the given type, which must hold a `T`.
+unsafe fn side_data<T>(frame: &AVFrame, type_: AVFrameSideDataType) -> Option<&T> {
+    let side_data = ffi::av_frame_get_side_data(frame, type_).as_ref()?;
+    if side_data.data.is_null() || side_data.size < std::mem::size_of::<T>() {
+        return None;
+    }
+    Some(&*side_data.data.cast::<T>())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    unsafe fn alloc_yuv420p_frame() -> *mut AVFrame {
+        let frame = alloc_frame().unwrap();
+        (*frame).format = ffi::AV_PIX_FMT_YUV420P;
+        (*frame).width = 64;
+        (*frame).height = 48;
+        assert_eq!(ffi::av_frame_get_buffer(frame, 0), 0);
+        frame
+    }
+
+    #[test]
+    fn test_writable() {
+        unsafe {
+            let mut frame = alloc_yuv420p_frame();
+            assert!(is_writable(&*frame));
+
+            let mut shared_frame = ffi::av_frame_alloc();
+            assert_eq!(ffi::av_frame_ref(shared_frame, frame), 0);
+            assert!(!is_writable(&*frame));
+            assert!(!is_writable(&*shared_frame));
+
+            make_writable(&mut *frame).unwrap();
+            assert!(is_writable(&*frame));
+            assert!(is_writable(&*shared_frame));
+
+            ffi::av_frame_free(&mut shared_frame);
+            ffi::av_frame_free(&mut frame);
+        }
+    }
+
+    #[test]
+    fn test_yuv420p_planes() {
+        unsafe {
+            let mut frame = alloc_yuv420p_frame();
+            let linesize = (*frame).linesize;
+            assert_eq!(
+                (*frame).plane_mut(0).unwrap().len(),
+                linesize[0] as usize * 48
+            );
+            assert_eq!(
+                (*frame).plane_mut(1).unwrap().len(),
+                linesize[1] as usize * 24
+            );
+            assert_eq!(
+                (*frame).plane_mut(2).unwrap().len(),
+                linesize[2] as usize * 24
+            );
+            assert!((*frame).plane_mut(3).is_none());
+
+            // A plane past the end of its buffer is not accessible
+            (*frame).height = 4800;
+            assert!((*frame).plane(0).is_none());
+            (*frame).height = -48;
+            assert!((*frame).plane(0).is_none());
+            ffi::av_frame_free(&mut frame);
+        }
+    }
+
+    #[test]
+    fn test_apply_cropping() {
+        let mut frame = unsafe { Frame::from_raw(alloc_yuv420p_frame()) }.unwrap();
+        let data = frame.data[0];
+        let linesize = frame.linesize[0] as usize;
+        let cropping = Crop {
+            top: 8,
+            bottom: 8,
+            left: 0,
+            right: 16,
+        };
+        set_crop(&mut frame, cropping);
+        assert_eq!(crop(&frame), cropping);
+
+        apply_cropping(&mut frame, ffi::AV_FRAME_CROP_UNALIGNED as i32).unwrap();
+        assert_eq!((frame.width, frame.height), (48, 32));
+        assert_eq!(frame.data[0], unsafe { data.add(8 * linesize) });
+        assert_eq!(crop(&frame), Crop::default());
+
+        set_crop(
+            &mut frame,
+            Crop {
+                right: 64,
+                ..Crop::default()
+            },
+        );
+        assert!(apply_cropping(&mut frame, 0).is_err());
+        assert_eq!((frame.width, frame.height), (48, 32));
+    }
+
+    #[test]
+    fn test_timestamps() {
+        let mut frame = Frame::new().unwrap();
+        assert_eq!(best_effort_timestamp(&frame), None);
+        assert_eq!(duration(&frame), None);
+
+        frame.pts = 3;
+        frame.best_effort_timestamp = 3600;
+        frame.duration = 3600;
+        assert_eq!(best_effort_timestamp(&frame), Some(3600));
+        assert_eq!(duration(&frame), Some(3600));
+    }
+
+    #[test]
+    fn test_mastering_display() {
+        let mut frame = Frame::new().unwrap();
+        assert!(mastering_display(&frame).is_none());
+
+        let mut metadata: AVMasteringDisplayMetadata = unsafe { std::mem::zeroed() };
+        metadata.min_luminance = ffi::AVRational {
+            num: 50,
+            den: 10000,
+        };
+        metadata.max_luminance = ffi::AVRational { num: 1000, den: 1 };
+        metadata.has_luminance = 1;
+        set_mastering_display(&mut frame, &metadata).unwrap();
+        set_mastering_display(&mut frame, &metadata).unwrap();
+        assert_eq!(frame.nb_side_data, 1);
+
+        let attached = mastering_display(&frame).unwrap();
+        assert_eq!(attached.has_luminance, 1);
+        assert_eq!(attached.max_luminance.num, 1000);
+        assert_eq!(attached.min_luminance.num, 50);
+        assert!(hdr_plus(&frame).is_none());
+    }
+
+    #[test]
+    fn test_motion_vectors() {
+        let mut frame = Frame::new().unwrap();
+        assert!(motion_vectors(&frame).is_empty());
+
+        let mut mv: AVMotionVector = unsafe { std::mem::zeroed() };
+        mv.source = -1;
+        mv.w = 16;
+        mv.h = 16;
+        mv.motion_scale = 4;
+        let mvs = [
+            AVMotionVector {
+                dst_x: 8,
+                motion_x: 12,
+                ..mv
+            },
+            AVMotionVector {
+                dst_x: 24,
+                motion_y: -4,
+                ..mv
+            },
+        ];
+        unsafe {
+            let side_data = ffi::av_frame_new_side_data(
+                frame.as_mut_ptr(),
+                ffi::AV_FRAME_DATA_MOTION_VECTORS,
+                std::mem::size_of_val(&mvs),
+            );
+            ptr::copy_nonoverlapping(mvs.as_ptr(), (*side_data).data.cast(), mvs.len());
+        }
+
+        let attached = motion_vectors(&frame);
+        assert_eq!(attached.len(), 2);
+        assert_eq!((attached[0].dst_x, attached[0].motion_x), (8, 12));
+        assert_eq!((attached[1].dst_x, attached[1].motion_y), (24, -4));
+        assert_eq!(attached[1].motion_scale, 4);
+    }
+
+    #[test]
+    fn test_clone_ref() {
+        let frame = unsafe { Frame::from_raw(alloc_yuv420p_frame()) }.unwrap();
+        let clone = frame.clone_ref().unwrap();
+        assert_eq!(clone.data[0], frame.data[0]);
+        assert_eq!((clone.width, clone.height), (64, 48));
+        assert_eq!(unsafe { ffi::av_buffer_get_ref_count(frame.buf[0]) }, 2);
+        assert!(!is_writable(&frame));
+
+        drop(clone);
+        assert_eq!(unsafe { ffi::av_buffer_get_ref_count(frame.buf[0]) }, 1);
+        assert!(is_writable(&frame));
+    }
+
+    #[test]
+    fn test_send_frame() {
+        let frame = unsafe { Frame::from_raw(alloc_yuv420p_frame()) }.unwrap();
+        let data = frame.data[0];
+        let frame = frame.into_send().unwrap();
+        let mut frame = std::thread::spawn(move || frame)
+            .join()
+            .unwrap()
+            .into_inner();
+        assert_eq!(frame.data[0], data);
+
+        // Neither of the frames sharing the data can modify it
+        let mut clone = frame.clone_ref().unwrap().into_send().unwrap();
+        let clone = std::thread::spawn(move || {
+            assert!(clone.0.plane_mut(0).is_none());
+            clone
+        })
+        .join()
+        .unwrap();
+        assert!(frame.plane_mut(0).is_none());
+        drop(clone);
+        assert!(frame.plane_mut(0).is_some());
+
+        let mut hw_frame = Frame::new().unwrap();
+        hw_frame.format = ffi::AV_PIX_FMT_DRM_PRIME;
+        assert!(hw_frame.into_send().is_err());
+    }
+
+    #[test]
+    fn test_alloc_frame() {
+        unsafe {
+            let mut frame = alloc_frame().unwrap();
+            assert_eq!((*frame).format, -1);
+            ffi::av_frame_free(&mut frame);
+            assert!(frame.is_null());
+            // Freeing a null frame is a no-op
+            ffi::av_frame_free(&mut frame);
+        }
+
+        let mut frame = Frame::new().unwrap();
+        frame.width = 64;
+        assert_eq!(unsafe { (*frame.as_ptr()).width }, 64);
+    }
+}
@@ -0,0 +1,66 @@
+use crate::buffer::Buffer;
+use crate::ffi::{self, AVHWDeviceType};
+use std::ffi::CString;
+use std::ptr;
+
+/// Open a hardware device of the given type and create an `AVHWDeviceContext`
+/// for it.
+///
+/// `device` is a type-specific string identifying the device to open, `None`
+/// to use the default device of that type.
+pub fn create_device(type_: AVHWDeviceType, device: Option<&str>) -> Result<Buffer, String> {
+    let device = device
+        .map(CString::new)
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    let mut device_ctx = ptr::null_mut();
+    let ret = unsafe {
+        ffi::av_hwdevice_ctx_create(
+            &mut device_ctx,
+            type_,
+            device
+                .as_ref()
+                .map_or(ptr::null(), |device| device.as_ptr()),
+            ptr::null_mut(),
+            0,
+        )
+    };
+    if ret < 0 {
+        return Err(ffi::av_err2str(ret));
+    }
+    unsafe { Buffer::from_raw(device_ctx) }.ok_or_else(|| "Missing device context".to_string())
+}
+
+/// Create a Rockchip MPP device context.
+///
+/// The `rkmpp` device type is only available in the Rockchip FFmpeg fork,
+/// so it is looked up by name.
+pub fn create_rkmpp() -> Result<Buffer, String> {
+    let type_ = unsafe { ffi::av_hwdevice_find_type_by_name(c"rkmpp".as_ptr()) };
+    if type_ == ffi::AV_HWDEVICE_TYPE_NONE {
+        return Err("rkmpp device type is not supported".to_string());
+    }
+    create_device(type_, None)
+}
+
+/// Create a DRM device context from a DRM node, e.g. `/dev/dri/renderD128`.
+pub fn create_drm(render_node: &str) -> Result<Buffer, String> {
+    create_device(ffi::AV_HWDEVICE_TYPE_DRM, Some(render_node))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::path::Path;
+
+    const RENDER_NODE: &str = "/dev/dri/renderD128";
+
+    #[test]
+    fn test_create_drm() {
+        if !Path::new(RENDER_NODE).exists() {
+            return;
+        }
+        let device_ctx = create_drm(RENDER_NODE).unwrap();
+        assert_eq!(device_ctx.ref_count(), 1);
+    }
+}
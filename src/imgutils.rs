@@ -0,0 +1,194 @@
+use crate::ffi::{self, AVFrame, AVPixelFormat};
+use crate::pixdesc;
+use std::ptr;
+
+/// Size in bytes of an image whose linesizes are aligned to `align` bytes.
+pub fn buffer_size(
+    format: AVPixelFormat,
+    width: i32,
+    height: i32,
+    align: i32,
+) -> Result<usize, String> {
+    let ret = unsafe { ffi::av_image_get_buffer_size(format, width, height, align) };
+    if ret < 0 {
+        return Err(ffi::av_err2str(ret));
+    }
+    Ok(ret as usize)
+}
+
+/// Size in bytes of a tightly packed image, e.g. a frame of a raw video file.
+pub fn frame_size(format: AVPixelFormat, width: i32, height: i32) -> Result<usize, String> {
+    buffer_size(format, width, height, 1)
+}
+
+/// An image buffer allocated with `av_image_alloc`, e.g. a scratch buffer
+/// for pixel format conversions.
+#[derive(Debug)]
+pub struct Image {
+    data: [*mut u8; 4],
+    linesize: [i32; 4],
+    width: i32,
+    height: i32,
+    format: AVPixelFormat,
+    size: usize,
+}
+
+impl Image {
+    /// Allocate an image, the linesizes are aligned to `align` bytes.
+    pub fn new(width: i32, height: i32, format: AVPixelFormat, align: i32) -> Result<Self, String> {
+        let mut data = [ptr::null_mut(); 4];
+        let mut linesize = [0; 4];
+        let ret = unsafe {
+            ffi::av_image_alloc(
+                data.as_mut_ptr(),
+                linesize.as_mut_ptr(),
+                width,
+                height,
+                format,
+                align,
+            )
+        };
+        if ret < 0 {
+            return Err(ffi::av_err2str(ret));
+        }
+        Ok(Self {
+            data,
+            linesize,
+            width,
+            height,
+            format,
+            size: ret as usize,
+        })
+    }
+
+    pub fn width(&self) -> i32 {
+        self.width
+    }
+
+    pub fn height(&self) -> i32 {
+        self.height
+    }
+
+    pub fn format(&self) -> AVPixelFormat {
+        self.format
+    }
+
+    /// Total size of the allocation in bytes
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Linesizes of the planes, unused planes have a zero linesize
+    pub fn linesize(&self) -> &[i32; 4] {
+        &self.linesize
+    }
+
+    /// Data of a plane, `None` if the plane is absent.
+    pub fn plane(&self, index: usize) -> Option<&[u8]> {
+        let len = self.plane_len(index)?;
+        Some(unsafe { std::slice::from_raw_parts(self.data[index], len) })
+    }
+
+    /// Mutable data of a plane, `None` if the plane is absent.
+    pub fn plane_mut(&mut self, index: usize) -> Option<&mut [u8]> {
+        let len = self.plane_len(index)?;
+        Some(unsafe { std::slice::from_raw_parts_mut(self.data[index], len) })
+    }
+
+    /// Copy the data of a video frame with the same size and pixel format.
+    pub fn copy_from(&mut self, frame: &AVFrame) -> Result<(), String> {
+        if frame.format != self.format || frame.width != self.width || frame.height != self.height {
+            return Err(format!(
+                "Frame {}x{} (format {}) doesn't match image {}x{} (format {})",
+                frame.width, frame.height, frame.format, self.width, self.height, self.format
+            ));
+        }
+        if pixdesc::is_hwaccel(frame.format) {
+            return Err("Cannot copy hardware frame data".to_string());
+        }
+        unsafe {
+            ffi::av_image_copy(
+                self.data.as_ptr(),
+                self.linesize.as_ptr(),
+                frame.data.as_ptr().cast(),
+                frame.linesize.as_ptr(),
+                self.format,
+                self.width,
+                self.height,
+            );
+        }
+        Ok(())
+    }
+
+    fn plane_len(&self, index: usize) -> Option<usize> {
+        if index >= self.data.len() || self.data[index].is_null() {
+            return None;
+        }
+        let height = pixdesc::plane_height(self.format, self.height, index)?;
+        Some(self.linesize[index] as usize * height as usize)
+    }
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        // All the planes share the buffer of the first one
+        unsafe { ffi::av_freep(ptr::from_mut(&mut self.data[0]).cast()) };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::frame::Frame;
+
+    #[test]
+    fn test_frame_size() {
+        assert_eq!(
+            frame_size(ffi::AV_PIX_FMT_YUV420P, 1920, 1080),
+            Ok(1920 * 1080 * 3 / 2)
+        );
+        assert_eq!(
+            frame_size(ffi::AV_PIX_FMT_NV12, 1920, 1080),
+            Ok(1920 * 1080 * 3 / 2)
+        );
+        // The width is aligned to 1024 pixels, giving 2048 and 1024 bytes linesizes
+        assert_eq!(
+            buffer_size(ffi::AV_PIX_FMT_YUV420P, 1920, 1080, 1024),
+            Ok(2048 * 1080 + 2 * 1024 * 540)
+        );
+        assert!(frame_size(ffi::AV_PIX_FMT_NONE, 1920, 1080).is_err());
+        assert!(frame_size(ffi::AV_PIX_FMT_NV12, -1, 1080).is_err());
+    }
+
+    #[test]
+    fn test_nv12_image() {
+        let image = Image::new(64, 64, ffi::AV_PIX_FMT_NV12, 1).unwrap();
+        let size = unsafe { ffi::av_image_get_buffer_size(ffi::AV_PIX_FMT_NV12, 64, 64, 1) };
+        assert_eq!(image.size(), size as usize);
+        assert_eq!(image.plane(0).unwrap().len(), 64 * 64);
+        assert_eq!(image.plane(1).unwrap().len(), 64 * 32);
+        assert!(image.plane(2).is_none());
+    }
+
+    #[test]
+    fn test_copy_from_frame() {
+        let mut frame = Frame::new().unwrap();
+        frame.format = ffi::AV_PIX_FMT_NV12;
+        frame.width = 64;
+        frame.height = 64;
+        assert_eq!(
+            unsafe { ffi::av_frame_get_buffer(frame.as_mut_ptr(), 0) },
+            0
+        );
+        frame.plane_mut(0).unwrap().fill(16);
+        frame.plane_mut(1).unwrap().fill(128);
+
+        let mut image = Image::new(64, 64, ffi::AV_PIX_FMT_NV12, 16).unwrap();
+        image.copy_from(&frame).unwrap();
+        assert!(image.plane(0).unwrap().iter().all(|&v| v == 16));
+        assert!(image.plane(1).unwrap().iter().all(|&v| v == 128));
+
+        let mut other = Image::new(32, 32, ffi::AV_PIX_FMT_NV12, 16).unwrap();
+        assert!(other.copy_from(&frame).is_err());
+    }
+}
@@ -0,0 +1,33 @@
+//! Compile-time checks of the layout of a few types the bindings are commonly
+//! used through. A mismatch means the bindings were generated for another
+//! target (e.g. a wrong clang target when cross compiling), so fail the build
+//! instead of corrupting memory at runtime.
+
+use crate::ffi::{AVFrame, AVPacket, AVRational};
+use core::ffi::c_int;
+use core::mem::{align_of, offset_of, size_of};
+
+const PTR_SIZE: usize = size_of::<*const u8>();
+const INT_SIZE: usize = size_of::<c_int>();
+
+const _: () = assert!(size_of::<AVRational>() == 8);
+const _: () = assert!(align_of::<AVRational>() == 4);
+
+// `AVFrame` starts with `data[8]`, `linesize[8]`, `extended_data`, `width`,
+// `height`, `nb_samples` and `format`
+const _: () = assert!(offset_of!(AVFrame, data) == 0);
+const _: () = assert!(offset_of!(AVFrame, linesize) == 8 * PTR_SIZE);
+const _: () = assert!(offset_of!(AVFrame, extended_data) == 8 * PTR_SIZE + 8 * INT_SIZE);
+const _: () = assert!(offset_of!(AVFrame, width) == 9 * PTR_SIZE + 8 * INT_SIZE);
+const _: () = assert!(offset_of!(AVFrame, format) == 9 * PTR_SIZE + 11 * INT_SIZE);
+
+// `AVPacket` starts with `buf`, `pts`, `dts`, `data` and `size`
+const PACKET_PTS_OFFSET: usize = if PTR_SIZE > align_of::<i64>() {
+    PTR_SIZE
+} else {
+    align_of::<i64>()
+};
+const _: () = assert!(offset_of!(AVPacket, pts) == PACKET_PTS_OFFSET);
+const _: () = assert!(offset_of!(AVPacket, dts) == PACKET_PTS_OFFSET + 8);
+const _: () = assert!(offset_of!(AVPacket, data) == PACKET_PTS_OFFSET + 16);
+const _: () = assert!(offset_of!(AVPacket, size) == PACKET_PTS_OFFSET + 16 + PTR_SIZE);
@@ -1,4 +1,43 @@
+#![cfg_attr(all(not(feature = "std"), not(test)), no_std)]
+
+#[cfg(not(any(feature = "std", feature = "no_std")))]
+compile_error!("Either the `std` or the `no_std` feature must be enabled");
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod avio;
 mod avutil;
+pub mod buffer;
+#[cfg(feature = "std")]
+pub mod codec;
+pub mod codec_flags;
+pub mod csp;
+#[cfg(feature = "std")]
+pub mod filter;
+#[cfg(feature = "std")]
+pub mod format;
+#[cfg(feature = "std")]
+pub mod frame;
+#[cfg(feature = "std")]
+pub mod hwdevice;
+#[cfg(feature = "std")]
+pub mod imgutils;
+mod layout_asserts;
+#[cfg(feature = "std")]
+pub mod logging;
+pub mod mathematics;
+#[cfg(feature = "std")]
+pub mod opt;
+pub mod packet;
+pub mod parseutils;
+pub mod pixdesc;
+#[cfg(feature = "std")]
+pub mod swresample;
+#[cfg(feature = "std")]
+pub mod swscale;
+pub mod util;
+pub mod version;
 
 #[allow(
     non_snake_case,
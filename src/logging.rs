@@ -0,0 +1,126 @@
+use crate::ffi;
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::sync::atomic::{AtomicI32, AtomicUsize, Ordering};
+use std::sync::RwLock;
+
+/// Size of the buffer a log line is rendered into, the same as the one
+/// `av_log_default_callback` uses. Longer lines are truncated.
+const LINE_SIZE: usize = 1024;
+
+type FormatLineFn<V> = unsafe extern "C" fn(
+    *mut c_void,
+    c_int,
+    *const c_char,
+    V,
+    *mut c_char,
+    c_int,
+    *mut c_int,
+) -> c_int;
+
+type SetCallbackFn<V> =
+    unsafe extern "C" fn(Option<unsafe extern "C" fn(*mut c_void, c_int, *const c_char, V)>);
+
+type LogCallback = fn(i32, &str);
+
+static LOG_CALLBACK: RwLock<Option<LogCallback>> = RwLock::new(None);
+/// Persistent `print_prefix` state required by `av_log_format_line2`.
+static PRINT_PREFIX: AtomicI32 = AtomicI32::new(1);
+/// `av_log_format_line2` stored as an address, see `install_trampoline`.
+static FORMAT_LINE: AtomicUsize = AtomicUsize::new(0);
+
+/// Route FFmpeg log messages into `callback` instead of stderr.
+///
+/// Messages above the level set with [`set_log_level`] are dropped before
+/// formatting. The callback is invoked from whatever thread FFmpeg logs on,
+/// and it must not panic as the panic can't unwind through FFmpeg.
+pub fn set_log_callback(callback: fn(level: i32, msg: &str)) {
+    *LOG_CALLBACK.write().unwrap_or_else(|e| e.into_inner()) = Some(callback);
+    unsafe { install_trampoline(ffi::av_log_set_callback, ffi::av_log_format_line2) };
+}
+
+/// Set the log level, see `AV_LOG_*` constants.
+pub fn set_log_level(level: i32) {
+    unsafe { ffi::av_log_set_level(level) };
+}
+
+/// `va_list` is bound differently depending on the target architecture
+/// (a pointer on x86_64, a struct on aarch64), so the trampoline is generic
+/// over it and the concrete type is inferred from the bound functions.
+unsafe fn install_trampoline<V>(set_callback: SetCallbackFn<V>, format_line: FormatLineFn<V>) {
+    FORMAT_LINE.store(format_line as usize, Ordering::Release);
+    set_callback(Some(log_trampoline::<V>));
+}
+
+unsafe extern "C" fn log_trampoline<V>(avcl: *mut c_void, level: c_int, fmt: *const c_char, vl: V) {
+    if level > ffi::av_log_get_level() {
+        return;
+    }
+    let callback = match LOG_CALLBACK.read() {
+        Ok(callback) => *callback,
+        Err(_) => return,
+    };
+    let Some(callback) = callback else {
+        return;
+    };
+    let format_line: FormatLineFn<V> = std::mem::transmute(FORMAT_LINE.load(Ordering::Acquire));
+
+    let mut line = [0 as c_char; LINE_SIZE];
+    let mut print_prefix = PRINT_PREFIX.load(Ordering::Relaxed);
+    let ret = format_line(
+        avcl,
+        level,
+        fmt,
+        vl,
+        line.as_mut_ptr(),
+        LINE_SIZE as c_int,
+        &mut print_prefix,
+    );
+    PRINT_PREFIX.store(print_prefix, Ordering::Relaxed);
+    if ret < 0 {
+        return;
+    }
+
+    let msg = CStr::from_ptr(line.as_ptr()).to_string_lossy();
+    callback(level, &msg);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ptr;
+    use std::sync::Mutex;
+
+    static MESSAGES: Mutex<Vec<(i32, String)>> = Mutex::new(Vec::new());
+
+    fn record(level: i32, msg: &str) {
+        MESSAGES.lock().unwrap().push((level, msg.to_string()));
+    }
+
+    #[test]
+    fn test_log_callback() {
+        let log_level = unsafe { ffi::av_log_get_level() };
+        set_log_level(ffi::AV_LOG_INFO as i32);
+        set_log_callback(record);
+        unsafe {
+            ffi::av_log(
+                ptr::null_mut(),
+                ffi::AV_LOG_ERROR as c_int,
+                c"hello %s\n".as_ptr(),
+                c"world".as_ptr(),
+            );
+            ffi::av_log(
+                ptr::null_mut(),
+                ffi::AV_LOG_DEBUG as c_int,
+                c"filtered out\n".as_ptr(),
+            );
+        }
+        // The callback and the level are global, restore them for the other tests
+        *LOG_CALLBACK.write().unwrap() = None;
+        unsafe { ffi::av_log_set_callback(Some(ffi::av_log_default_callback)) };
+        set_log_level(log_level);
+
+        let messages = MESSAGES.lock().unwrap();
+        assert!(messages.contains(&(ffi::AV_LOG_ERROR as i32, "hello world\n".to_string())));
+        assert!(!messages.iter().any(|(_, msg)| msg.contains("filtered out")));
+    }
+}
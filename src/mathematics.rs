@@ -0,0 +1,44 @@
+use crate::ffi::{self, AVRational};
+use core::cmp::Ordering;
+
+/// Compare two timestamps each in its own time base, e.g. the DTS of packets
+/// of different streams to interleave them for muxing.
+///
+/// The result is undefined if one of the timestamps doesn't fit into `i64`
+/// when rescaled to the other time base.
+pub fn compare_ts(ts_a: i64, tb_a: AVRational, ts_b: i64, tb_b: AVRational) -> Ordering {
+    unsafe { ffi::av_compare_ts(ts_a, tb_a, ts_b, tb_b) }.cmp(&0)
+}
+
+/// Rescale a timestamp from the `src_tb` to the `dst_tb` time base, rounding
+/// to the nearest value.
+pub fn rescale_q(ts: i64, src_tb: AVRational, dst_tb: AVRational) -> i64 {
+    unsafe { ffi::av_rescale_q(ts, src_tb, dst_tb) }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const TB_25: AVRational = AVRational { num: 1, den: 25 };
+    const TB_90K: AVRational = AVRational { num: 1, den: 90000 };
+
+    #[test]
+    fn test_compare_ts() {
+        // The 3rd frame at 25 fps is at 120ms
+        assert_eq!(compare_ts(3, TB_25, 10800, TB_90K), Ordering::Equal);
+        assert_eq!(compare_ts(3, TB_25, 10799, TB_90K), Ordering::Greater);
+        assert_eq!(compare_ts(3, TB_25, 10801, TB_90K), Ordering::Less);
+        assert_eq!(compare_ts(10801, TB_90K, 3, TB_25), Ordering::Greater);
+        assert_eq!(compare_ts(-1, TB_25, 0, TB_90K), Ordering::Less);
+    }
+
+    #[test]
+    fn test_rescale_q() {
+        assert_eq!(rescale_q(3, TB_25, TB_90K), 10800);
+        assert_eq!(rescale_q(10800, TB_90K, TB_25), 3);
+        // 1799 / 3600 of a frame is rounded down
+        assert_eq!(rescale_q(1799, TB_90K, TB_25), 0);
+        assert_eq!(rescale_q(1800, TB_90K, TB_25), 1);
+    }
+}
@@ -0,0 +1,179 @@
+use crate::ffi::{self, AVClass, AVOption, AVOptionType};
+use std::ffi::{c_char, c_void, CStr};
+use std::ptr;
+
+/// Description of an option of an AVOptions-enabled struct
+#[derive(Clone, Debug)]
+pub struct OptionInfo {
+    pub name: String,
+    pub type_: AVOptionType,
+    pub help: Option<String>,
+    /// Default value rendered as a string, `None` if the option has no default
+    pub default: Option<String>,
+    pub min: f64,
+    pub max: f64,
+    /// The logical unit the option and its named constants belong to
+    pub unit: Option<String>,
+}
+
+impl OptionInfo {
+    /// Human readable name of the option type, the same as `ffmpeg -h` shows
+    pub fn type_name(&self) -> &'static str {
+        // Array options exist since FFmpeg 7
+        #[cfg(feature = "ffmpeg7")]
+        let type_ = self.type_ & !ffi::AV_OPT_TYPE_FLAG_ARRAY;
+        #[cfg(not(feature = "ffmpeg7"))]
+        let type_ = self.type_;
+        match type_ {
+            ffi::AV_OPT_TYPE_FLAGS => "flags",
+            ffi::AV_OPT_TYPE_INT => "int",
+            ffi::AV_OPT_TYPE_INT64 => "int64",
+            #[cfg(feature = "ffmpeg7")]
+            ffi::AV_OPT_TYPE_UINT => "uint",
+            ffi::AV_OPT_TYPE_UINT64 => "uint64",
+            ffi::AV_OPT_TYPE_DOUBLE => "double",
+            ffi::AV_OPT_TYPE_FLOAT => "float",
+            ffi::AV_OPT_TYPE_STRING => "string",
+            ffi::AV_OPT_TYPE_RATIONAL => "rational",
+            ffi::AV_OPT_TYPE_BINARY => "binary",
+            ffi::AV_OPT_TYPE_DICT => "dictionary",
+            ffi::AV_OPT_TYPE_CONST => "const",
+            ffi::AV_OPT_TYPE_IMAGE_SIZE => "image_size",
+            ffi::AV_OPT_TYPE_PIXEL_FMT => "pix_fmt",
+            ffi::AV_OPT_TYPE_SAMPLE_FMT => "sample_fmt",
+            ffi::AV_OPT_TYPE_VIDEO_RATE => "video_rate",
+            ffi::AV_OPT_TYPE_DURATION => "duration",
+            ffi::AV_OPT_TYPE_COLOR => "color",
+            ffi::AV_OPT_TYPE_BOOL => "boolean",
+            ffi::AV_OPT_TYPE_CHLAYOUT => "channel_layout",
+            _ => "unknown",
+        }
+    }
+}
+
+/// List all the options of an AVOptions-enabled struct, including the named
+/// constants (`AV_OPT_TYPE_CONST`) of the option units.
+///
+/// # Safety
+/// `obj` must be null or point to a struct whose first member is a pointer
+/// to an `AVClass`.
+pub unsafe fn list_options(obj: *mut c_void) -> Vec<OptionInfo> {
+    let mut options = vec![];
+    if obj.is_null() {
+        return options;
+    }
+    let mut opt: *const AVOption = ptr::null();
+    loop {
+        opt = ffi::av_opt_next(obj, opt);
+        let Some(option) = opt.as_ref() else {
+            break;
+        };
+        options.push(OptionInfo {
+            name: to_string(option.name).unwrap_or_default(),
+            type_: option.type_,
+            help: to_string(option.help),
+            default: default_value(option),
+            min: option.min,
+            max: option.max,
+            unit: to_string(option.unit),
+        });
+    }
+    options
+}
+
+/// Name of the class of an AVOptions-enabled struct, e.g. `AVFormatContext`.
+///
+/// # Safety
+/// `obj` must be null or point to a struct whose first member is a pointer
+/// to an `AVClass`.
+pub unsafe fn class_name(obj: *const c_void) -> Option<String> {
+    if obj.is_null() {
+        return None;
+    }
+    let class = (*obj.cast::<*const AVClass>()).as_ref()?;
+    to_string(class.class_name)
+}
+
+unsafe fn default_value(option: &AVOption) -> Option<String> {
+    #[cfg(feature = "ffmpeg7")]
+    if option.type_ & ffi::AV_OPT_TYPE_FLAG_ARRAY != 0 {
+        return option
+            .default_val
+            .arr
+            .as_ref()
+            .and_then(|arr| to_string(arr.def));
+    }
+    match option.type_ {
+        ffi::AV_OPT_TYPE_FLAGS
+        | ffi::AV_OPT_TYPE_INT
+        | ffi::AV_OPT_TYPE_INT64
+        | ffi::AV_OPT_TYPE_CONST
+        | ffi::AV_OPT_TYPE_PIXEL_FMT
+        | ffi::AV_OPT_TYPE_SAMPLE_FMT
+        | ffi::AV_OPT_TYPE_DURATION => Some(option.default_val.i64_.to_string()),
+        #[cfg(feature = "ffmpeg7")]
+        ffi::AV_OPT_TYPE_UINT => Some(option.default_val.i64_.to_string()),
+        ffi::AV_OPT_TYPE_UINT64 => Some((option.default_val.i64_ as u64).to_string()),
+        ffi::AV_OPT_TYPE_BOOL => Some(
+            match option.default_val.i64_ {
+                0 => "false",
+                1 => "true",
+                _ => "auto",
+            }
+            .to_string(),
+        ),
+        ffi::AV_OPT_TYPE_DOUBLE | ffi::AV_OPT_TYPE_FLOAT => {
+            Some(option.default_val.dbl.to_string())
+        }
+        ffi::AV_OPT_TYPE_RATIONAL => {
+            let q = option.default_val.q;
+            Some(format!("{}/{}", q.num, q.den))
+        }
+        ffi::AV_OPT_TYPE_STRING
+        | ffi::AV_OPT_TYPE_IMAGE_SIZE
+        | ffi::AV_OPT_TYPE_VIDEO_RATE
+        | ffi::AV_OPT_TYPE_COLOR
+        | ffi::AV_OPT_TYPE_DICT
+        | ffi::AV_OPT_TYPE_CHLAYOUT => to_string(option.default_val.str_),
+        _ => None,
+    }
+}
+
+unsafe fn to_string(s: *const c_char) -> Option<String> {
+    if s.is_null() {
+        return None;
+    }
+    Some(CStr::from_ptr(s).to_string_lossy().into_owned())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_list_codec_context_options() {
+        unsafe {
+            let mut ctx = ffi::avcodec_alloc_context3(ptr::null());
+            assert!(!ctx.is_null());
+            let options = list_options(ctx.cast());
+            assert!(!options.is_empty());
+            let bit_rate = options.iter().find(|opt| opt.name == "b").unwrap();
+            assert_eq!(bit_rate.type_name(), "int64");
+            assert!(bit_rate.help.is_some());
+            ffi::avcodec_free_context(&mut ctx);
+        }
+    }
+
+    #[test]
+    fn test_class_name() {
+        unsafe {
+            let mut ctx = ffi::avformat_alloc_context();
+            assert!(!ctx.is_null());
+            assert!(!(*ctx).av_class.is_null());
+            assert_eq!(class_name(ctx.cast()).as_deref(), Some("AVFormatContext"));
+            ffi::avformat_free_context(ctx);
+            ctx = ptr::null_mut();
+            assert_eq!(class_name(ctx.cast()), None);
+        }
+    }
+}
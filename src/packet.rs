@@ -0,0 +1,185 @@
+use crate::ffi::{self, AVPacket, AVPictureType, AVRational};
+use core::ffi::c_int;
+use core::ops::{Deref, DerefMut};
+use core::ptr::NonNull;
+
+/// The packet contains a keyframe
+pub const AV_PKT_FLAG_KEY: c_int = ffi::AV_PKT_FLAG_KEY as c_int;
+/// The packet content is corrupted
+pub const AV_PKT_FLAG_CORRUPT: c_int = ffi::AV_PKT_FLAG_CORRUPT as c_int;
+/// The packet is required to maintain valid decoder state but is not
+/// output and should be dropped after decoding
+pub const AV_PKT_FLAG_DISCARD: c_int = ffi::AV_PKT_FLAG_DISCARD as c_int;
+
+/// Check if the packet contains a keyframe.
+pub fn is_keyframe(packet: &AVPacket) -> bool {
+    packet.flags & AV_PKT_FLAG_KEY != 0
+}
+
+/// Statistics of the encoded frame exported by the encoder as
+/// `AV_PKT_DATA_QUALITY_STATS` side data.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QualityStats {
+    /// Quality of the frame in lambda units, the QP times `FF_QP2LAMBDA`
+    pub quality: u32,
+    /// Picture type of the frame, e.g. `AV_PICTURE_TYPE_I`
+    pub pict_type: AVPictureType,
+}
+
+/// Encoder statistics attached to the packet, `None` if the encoder doesn't
+/// export them.
+pub fn quality_stats(packet: &AVPacket) -> Option<QualityStats> {
+    let mut size = 0;
+    let data =
+        unsafe { ffi::av_packet_get_side_data(packet, ffi::AV_PKT_DATA_QUALITY_STATS, &mut size) };
+    // A little-endian 32-bit quality followed by the picture type byte
+    if data.is_null() || size < 5 {
+        return None;
+    }
+    let data = unsafe { core::slice::from_raw_parts(data, size) };
+    Some(QualityStats {
+        quality: u32::from_le_bytes([data[0], data[1], data[2], data[3]]),
+        pict_type: data[4] as AVPictureType,
+    })
+}
+
+/// Convert the timestamps and the duration of a packet from `src_tb` to
+/// `dst_tb`, e.g. from the encoder time base to the stream one before
+/// muxing. Unset timestamps stay unset.
+pub fn rescale_ts(packet: &mut AVPacket, src_tb: AVRational, dst_tb: AVRational) {
+    unsafe { ffi::av_packet_rescale_ts(packet, src_tb, dst_tb) };
+}
+
+/// Allocate an empty packet, `None` on allocation failure.
+///
+/// The packet must be freed with `av_packet_free`, see [`Packet`] for an
+/// owned alternative.
+pub fn alloc_packet() -> Option<*mut AVPacket> {
+    let packet = unsafe { ffi::av_packet_alloc() };
+    (!packet.is_null()).then_some(packet)
+}
+
+/// An owned `AVPacket`, freed with `av_packet_free` when dropped.
+#[derive(Debug)]
+pub struct Packet {
+    ptr: NonNull<AVPacket>,
+}
+
+impl Packet {
+    /// Allocate an empty packet, `None` on allocation failure.
+    pub fn new() -> Option<Self> {
+        unsafe { Self::from_raw(alloc_packet()?) }
+    }
+
+    /// Take ownership of a packet, `None` if `ptr` is null.
+    ///
+    /// # Safety
+    /// `ptr` must be a packet allocated with `av_packet_alloc` which is not
+    /// freed elsewhere.
+    pub unsafe fn from_raw(ptr: *mut AVPacket) -> Option<Self> {
+        NonNull::new(ptr).map(|ptr| Self { ptr })
+    }
+
+    /// Release the ownership of the packet without freeing it.
+    pub fn into_raw(self) -> *mut AVPacket {
+        let ptr = self.ptr.as_ptr();
+        core::mem::forget(self);
+        ptr
+    }
+
+    pub fn as_ptr(&self) -> *const AVPacket {
+        self.ptr.as_ptr()
+    }
+
+    pub fn as_mut_ptr(&mut self) -> *mut AVPacket {
+        self.ptr.as_ptr()
+    }
+}
+
+impl Deref for Packet {
+    type Target = AVPacket;
+
+    fn deref(&self) -> &AVPacket {
+        unsafe { self.ptr.as_ref() }
+    }
+}
+
+impl DerefMut for Packet {
+    fn deref_mut(&mut self) -> &mut AVPacket {
+        unsafe { self.ptr.as_mut() }
+    }
+}
+
+impl Drop for Packet {
+    fn drop(&mut self) {
+        let mut ptr = self.ptr.as_ptr();
+        unsafe { ffi::av_packet_free(&mut ptr) };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_alloc_packet() {
+        unsafe {
+            let mut packet = alloc_packet().unwrap();
+            assert_eq!((*packet).size, 0);
+            ffi::av_packet_free(&mut packet);
+            assert!(packet.is_null());
+            // Freeing a null packet is a no-op
+            ffi::av_packet_free(&mut packet);
+        }
+
+        let mut packet = Packet::new().unwrap();
+        assert_eq!(packet.pts, ffi::AV_NOPTS_VALUE);
+        packet.stream_index = 1;
+        assert_eq!(unsafe { (*packet.as_ptr()).stream_index }, 1);
+    }
+
+    #[test]
+    fn test_is_keyframe() {
+        let mut packet = Packet::new().unwrap();
+        assert!(!is_keyframe(&packet));
+        packet.flags |= AV_PKT_FLAG_KEY;
+        assert!(is_keyframe(&packet));
+        packet.flags = AV_PKT_FLAG_CORRUPT | AV_PKT_FLAG_DISCARD;
+        assert!(!is_keyframe(&packet));
+    }
+
+    #[test]
+    fn test_quality_stats() {
+        let mut packet = Packet::new().unwrap();
+        assert!(quality_stats(&packet).is_none());
+
+        let quality = 30 * ffi::FF_QP2LAMBDA;
+        unsafe {
+            let data = ffi::av_packet_new_side_data(
+                packet.as_mut_ptr(),
+                ffi::AV_PKT_DATA_QUALITY_STATS,
+                8,
+            );
+            core::ptr::copy_nonoverlapping(quality.to_le_bytes().as_ptr(), data, 4);
+            *data.add(4) = ffi::AV_PICTURE_TYPE_P as u8;
+        }
+        let stats = quality_stats(&packet).unwrap();
+        assert_eq!(stats.quality, quality);
+        assert_eq!(stats.pict_type, ffi::AV_PICTURE_TYPE_P);
+    }
+
+    #[test]
+    fn test_rescale_ts() {
+        let mut packet = Packet::new().unwrap();
+        packet.pts = 3;
+        packet.duration = 1;
+        rescale_ts(
+            &mut packet,
+            AVRational { num: 1, den: 25 },
+            AVRational { num: 1, den: 90000 },
+        );
+        assert_eq!(packet.pts, 10800);
+        assert_eq!(packet.duration, 3600);
+        assert_eq!(packet.dts, ffi::AV_NOPTS_VALUE);
+    }
+}
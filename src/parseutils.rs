@@ -0,0 +1,53 @@
+use crate::ffi;
+use alloc::ffi::CString;
+use core::ffi::c_int;
+
+/// Parse a video size, either as `WIDTHxHEIGHT` or as an abbreviation
+/// like `hd1080` or `vga`.
+///
+/// Returns `(width, height)`, `None` if the string is not a valid size.
+pub fn parse_video_size(s: &str) -> Option<(i32, i32)> {
+    let s = CString::new(s).ok()?;
+    let mut width: c_int = 0;
+    let mut height: c_int = 0;
+    let ret = unsafe { ffi::av_parse_video_size(&mut width, &mut height, s.as_ptr()) };
+    if ret < 0 {
+        return None;
+    }
+    Some((width, height))
+}
+
+/// Parse a time or a duration and return it in microseconds.
+///
+/// If `duration` is false the string is interpreted as a date
+/// (`[{YYYY-MM-DD|YYYYMMDD}[T|t| ]]{{HH:MM:SS[.m...]]]}|{HHMMSS[.m...]]]}}[Z]`
+/// or `now`), otherwise as a duration (`[-][HH:]MM:SS[.m...]` or
+/// `[-]S+[.m...][s|ms|us]`).
+pub fn parse_time(s: &str, duration: bool) -> Option<i64> {
+    let s = CString::new(s).ok()?;
+    let mut timeval = 0i64;
+    let ret = unsafe { ffi::av_parse_time(&mut timeval, s.as_ptr(), duration as c_int) };
+    if ret < 0 {
+        return None;
+    }
+    Some(timeval)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_video_size() {
+        assert_eq!(parse_video_size("hd1080"), Some((1920, 1080)));
+        assert_eq!(parse_video_size("640x480"), Some((640, 480)));
+        assert_eq!(parse_video_size("not a size"), None);
+    }
+
+    #[test]
+    fn test_parse_time() {
+        assert_eq!(parse_time("00:01:30.5", true), Some(90_500_000));
+        assert_eq!(parse_time("250ms", true), Some(250_000));
+        assert_eq!(parse_time("not a time", true), None);
+    }
+}
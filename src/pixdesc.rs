@@ -0,0 +1,89 @@
+use crate::ffi::{self, AVPixFmtDescriptor, AVPixelFormat};
+use alloc::ffi::CString;
+use alloc::string::String;
+use core::ffi::CStr;
+
+/// Descriptor of a pixel format, `None` if the format is unknown.
+pub fn descriptor(fmt: AVPixelFormat) -> Option<&'static AVPixFmtDescriptor> {
+    unsafe { ffi::av_pix_fmt_desc_get(fmt).as_ref() }
+}
+
+/// Pixel format by its name (e.g. `nv12`), as accepted by the FFmpeg CLI.
+pub fn pix_fmt_from_name(name: &str) -> Option<AVPixelFormat> {
+    let name = CString::new(name).ok()?;
+    let fmt = unsafe { ffi::av_get_pix_fmt(name.as_ptr()) };
+    (fmt != ffi::AV_PIX_FMT_NONE).then_some(fmt)
+}
+
+/// Name of a pixel format, `None` if the format is unknown.
+pub fn pix_fmt_name(fmt: AVPixelFormat) -> Option<String> {
+    let name = unsafe { ffi::av_get_pix_fmt_name(fmt) };
+    if name.is_null() {
+        return None;
+    }
+    Some(
+        unsafe { CStr::from_ptr(name) }
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+/// Number of planes of a pixel format, `None` if the format is unknown.
+pub fn count_planes(fmt: AVPixelFormat) -> Option<usize> {
+    let ret = unsafe { ffi::av_pix_fmt_count_planes(fmt) };
+    if ret < 0 {
+        return None;
+    }
+    Some(ret as usize)
+}
+
+/// Chroma subsampling of a pixel format as `(log2_chroma_w, log2_chroma_h)`,
+/// e.g. `(1, 1)` for YUV420P.
+pub fn chroma_subsampling(fmt: AVPixelFormat) -> Option<(u8, u8)> {
+    descriptor(fmt).map(|desc| (desc.log2_chroma_w, desc.log2_chroma_h))
+}
+
+/// Whether the pixel format describes hardware frames rather than data in memory
+pub fn is_hwaccel(fmt: AVPixelFormat) -> bool {
+    descriptor(fmt).is_some_and(|desc| desc.flags & ffi::AV_PIX_FMT_FLAG_HWACCEL as u64 != 0)
+}
+
+/// Height of a plane of an image of the given pixel format.
+///
+/// Planes 1 and 2 hold the chroma components and are subsampled vertically,
+/// the luma (or the first RGB) plane and the alpha plane are not.
+pub fn plane_height(fmt: AVPixelFormat, height: i32, plane: usize) -> Option<i32> {
+    let (_, log2_chroma_h) = chroma_subsampling(fmt)?;
+    if plane == 1 || plane == 2 {
+        // AV_CEIL_RSHIFT
+        Some(-((-height) >> log2_chroma_h))
+    } else {
+        Some(height)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_yuv420p() {
+        assert_eq!(count_planes(ffi::AV_PIX_FMT_YUV420P), Some(3));
+        assert_eq!(chroma_subsampling(ffi::AV_PIX_FMT_YUV420P), Some((1, 1)));
+        assert_eq!(plane_height(ffi::AV_PIX_FMT_YUV420P, 15, 0), Some(15));
+        assert_eq!(plane_height(ffi::AV_PIX_FMT_YUV420P, 15, 2), Some(8));
+        assert!(!is_hwaccel(ffi::AV_PIX_FMT_YUV420P));
+        assert!(is_hwaccel(ffi::AV_PIX_FMT_DRM_PRIME));
+    }
+
+    #[test]
+    fn test_pix_fmt_names() {
+        for name in ["nv12", "yuv420p"] {
+            let fmt = pix_fmt_from_name(name).unwrap();
+            assert_eq!(pix_fmt_name(fmt).as_deref(), Some(name));
+        }
+        assert_eq!(pix_fmt_from_name("nv12"), Some(ffi::AV_PIX_FMT_NV12));
+        assert_eq!(pix_fmt_from_name("not a format"), None);
+        assert_eq!(pix_fmt_name(ffi::AV_PIX_FMT_NONE), None);
+    }
+}
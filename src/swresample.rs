@@ -0,0 +1,195 @@
+use crate::ffi::{self, AVChannelLayout, AVSampleFormat, SwrContext};
+use crate::frame::Frame;
+use std::ffi::c_int;
+use std::ptr::{self, NonNull};
+
+/// Audio resampler converting between sample rates, sample formats and
+/// channel layouts.
+pub struct Resampler {
+    ctx: NonNull<SwrContext>,
+    in_sample_fmt: AVSampleFormat,
+    in_channels: usize,
+    out_ch_layout: AVChannelLayout,
+    out_sample_fmt: AVSampleFormat,
+    out_sample_rate: i32,
+}
+
+impl Resampler {
+    /// Allocate and initialize a resampler.
+    pub fn new(
+        in_ch_layout: &AVChannelLayout,
+        in_sample_fmt: AVSampleFormat,
+        in_sample_rate: i32,
+        out_ch_layout: &AVChannelLayout,
+        out_sample_fmt: AVSampleFormat,
+        out_sample_rate: i32,
+    ) -> Result<Self, String> {
+        let mut ctx = ptr::null_mut();
+        let ret = unsafe {
+            ffi::swr_alloc_set_opts2(
+                &mut ctx,
+                out_ch_layout,
+                out_sample_fmt,
+                out_sample_rate,
+                in_ch_layout,
+                in_sample_fmt,
+                in_sample_rate,
+                0,
+                ptr::null_mut(),
+            )
+        };
+        if ret < 0 {
+            return Err(ffi::av_err2str(ret));
+        }
+        let ctx = NonNull::new(ctx).ok_or_else(|| ffi::av_err2str(ffi::AVERROR(ffi::ENOMEM)))?;
+        let mut resampler = Self {
+            ctx,
+            in_sample_fmt,
+            in_channels: in_ch_layout.nb_channels as usize,
+            out_ch_layout: unsafe { std::mem::zeroed() },
+            out_sample_fmt,
+            out_sample_rate,
+        };
+        let ret =
+            unsafe { ffi::av_channel_layout_copy(&mut resampler.out_ch_layout, out_ch_layout) };
+        if ret < 0 {
+            return Err(ffi::av_err2str(ret));
+        }
+        let ret = unsafe { ffi::swr_init(resampler.ctx.as_ptr()) };
+        if ret < 0 {
+            return Err(ffi::av_err2str(ret));
+        }
+        Ok(resampler)
+    }
+
+    /// Convert audio samples into a frame of at most `out_samples` samples.
+    ///
+    /// `input` contains a slice per channel for planar sample formats and a
+    /// single slice with interleaved samples for packed ones. An empty
+    /// `input` flushes the samples buffered by the resampler.
+    pub fn convert(&mut self, input: &[&[u8]], out_samples: i32) -> Result<Frame, String> {
+        let (in_samples, in_data) = self.input_planes(input)?;
+
+        let mut frame = Frame::new().ok_or_else(|| ffi::av_err2str(ffi::AVERROR(ffi::ENOMEM)))?;
+        frame.format = self.out_sample_fmt;
+        frame.sample_rate = self.out_sample_rate;
+        frame.nb_samples = out_samples;
+        let ret = unsafe { ffi::av_channel_layout_copy(&mut frame.ch_layout, &self.out_ch_layout) };
+        if ret < 0 {
+            return Err(ffi::av_err2str(ret));
+        }
+        let ret = unsafe { ffi::av_frame_get_buffer(frame.as_mut_ptr(), 0) };
+        if ret < 0 {
+            return Err(ffi::av_err2str(ret));
+        }
+
+        let ret = unsafe {
+            ffi::swr_convert(
+                self.ctx.as_ptr(),
+                frame.extended_data.cast_const(),
+                out_samples,
+                in_data.as_ref().map_or(ptr::null(), |data| data.as_ptr()),
+                in_samples,
+            )
+        };
+        if ret < 0 {
+            return Err(ffi::av_err2str(ret));
+        }
+        frame.nb_samples = ret;
+        Ok(frame)
+    }
+
+    /// Delay of the next input sample relative to the next output sample,
+    /// expressed in `1 / base` seconds units.
+    pub fn delay(&self, base: i64) -> i64 {
+        unsafe { ffi::swr_get_delay(self.ctx.as_ptr(), base) }
+    }
+
+    /// Upper bound of the number of output samples for `in_samples` input
+    /// samples, including the buffered ones.
+    pub fn out_samples(&self, in_samples: i32) -> Result<i32, String> {
+        let ret = unsafe { ffi::swr_get_out_samples(self.ctx.as_ptr(), in_samples) };
+        if ret < 0 {
+            return Err(ffi::av_err2str(ret));
+        }
+        Ok(ret)
+    }
+
+    /// Number of input samples and plane pointers, `None` when flushing.
+    fn input_planes(&self, input: &[&[u8]]) -> Result<(c_int, Option<Vec<*const u8>>), String> {
+        if input.is_empty() {
+            return Ok((0, None));
+        }
+        let is_planar = unsafe { ffi::av_sample_fmt_is_planar(self.in_sample_fmt) } != 0;
+        let (num_planes, channels_per_plane) = if is_planar {
+            (self.in_channels, 1)
+        } else {
+            (1, self.in_channels)
+        };
+        if input.len() != num_planes {
+            return Err(format!(
+                "Expected {num_planes} input planes, got {}",
+                input.len()
+            ));
+        }
+        let bytes_per_sample = unsafe { ffi::av_get_bytes_per_sample(self.in_sample_fmt) } as usize;
+        let plane_len = input[0].len();
+        if bytes_per_sample == 0
+            || !plane_len.is_multiple_of(bytes_per_sample * channels_per_plane)
+            || input.iter().any(|plane| plane.len() != plane_len)
+        {
+            return Err("Input planes don't contain a whole number of samples".to_string());
+        }
+        let in_samples = plane_len / (bytes_per_sample * channels_per_plane);
+        Ok((
+            in_samples as c_int,
+            Some(input.iter().map(|plane| plane.as_ptr()).collect()),
+        ))
+    }
+}
+
+impl Drop for Resampler {
+    fn drop(&mut self) {
+        let mut ctx = self.ctx.as_ptr();
+        unsafe {
+            ffi::swr_free(&mut ctx);
+            ffi::av_channel_layout_uninit(&mut self.out_ch_layout);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_resample_sine() {
+        let mut mono: AVChannelLayout = unsafe { std::mem::zeroed() };
+        unsafe { ffi::av_channel_layout_default(&mut mono, 1) };
+        let mut resampler = Resampler::new(
+            &mono,
+            ffi::AV_SAMPLE_FMT_FLT,
+            44100,
+            &mono,
+            ffi::AV_SAMPLE_FMT_S16,
+            48000,
+        )
+        .unwrap();
+
+        // 100ms of a 440Hz sine
+        let samples = (0..4410)
+            .map(|i| (i as f32 * 440.0 * 2.0 * std::f32::consts::PI / 44100.0).sin())
+            .flat_map(f32::to_ne_bytes)
+            .collect::<Vec<_>>();
+        let out_samples = resampler.out_samples(4410).unwrap();
+        let frame = resampler.convert(&[&samples], out_samples).unwrap();
+        let flushed = resampler.convert(&[], out_samples).unwrap();
+
+        assert_eq!(frame.sample_rate, 48000);
+        assert_eq!(frame.ch_layout.nb_channels, 1);
+        let total = frame.nb_samples + flushed.nb_samples;
+        assert!((4780..=4820).contains(&total), "{total} samples resampled");
+
+        assert!(resampler.convert(&[&samples[..3]], out_samples).is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use crate::ffi::{self, AVFrame, AVPixelFormat, SwsContext};
+use crate::frame::Frame;
+use std::ptr::{self, NonNull};
+
+/// Software scaler converting video frames between sizes and pixel formats,
+/// e.g. a CPU fallback when RGA is not available.
+pub struct Scaler {
+    ctx: NonNull<SwsContext>,
+    src_width: i32,
+    src_height: i32,
+    src_format: AVPixelFormat,
+    dst_width: i32,
+    dst_height: i32,
+    dst_format: AVPixelFormat,
+}
+
+impl Scaler {
+    /// Allocate a scaler, `flags` selects the scaling algorithm, e.g.
+    /// `SWS_BILINEAR`.
+    pub fn new(
+        src_width: i32,
+        src_height: i32,
+        src_format: AVPixelFormat,
+        dst_width: i32,
+        dst_height: i32,
+        dst_format: AVPixelFormat,
+        flags: i32,
+    ) -> Result<Self, String> {
+        let ctx = unsafe {
+            ffi::sws_getContext(
+                src_width,
+                src_height,
+                src_format,
+                dst_width,
+                dst_height,
+                dst_format,
+                flags,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null(),
+            )
+        };
+        // Unsupported formats and sizes fail the same way as the allocation
+        let ctx = NonNull::new(ctx).ok_or_else(|| ffi::av_err2str(ffi::AVERROR(ffi::EINVAL)))?;
+        Ok(Self {
+            ctx,
+            src_width,
+            src_height,
+            src_format,
+            dst_width,
+            dst_height,
+            dst_format,
+        })
+    }
+
+    /// Convert a frame into a newly allocated one, keeping its pts.
+    ///
+    /// The frame must have the source size and pixel format of the scaler.
+    pub fn scale_frame(&mut self, src: &AVFrame) -> Result<Frame, String> {
+        if src.format != self.src_format
+            || src.width != self.src_width
+            || src.height != self.src_height
+        {
+            return Err(format!(
+                "Frame {}x{} (format {}) doesn't match scaler input {}x{} (format {})",
+                src.width, src.height, src.format, self.src_width, self.src_height, self.src_format
+            ));
+        }
+        let mut dst = Frame::new().ok_or_else(|| ffi::av_err2str(ffi::AVERROR(ffi::ENOMEM)))?;
+        dst.format = self.dst_format;
+        dst.width = self.dst_width;
+        dst.height = self.dst_height;
+        // `sws_scale_frame` appeared in libswscale 6 (FFmpeg 5.0)
+        #[cfg(has_sws_scale_frame)]
+        let ret = unsafe { ffi::sws_scale_frame(self.ctx.as_ptr(), dst.as_mut_ptr(), src) };
+        #[cfg(not(has_sws_scale_frame))]
+        let ret = unsafe { self.scale_slices(src, &mut dst) };
+        if ret < 0 {
+            return Err(ffi::av_err2str(ret));
+        }
+        dst.pts = src.pts;
+        Ok(dst)
+    }
+
+    /// Convert the whole frame with `sws_scale`, allocating the destination
+    /// buffers.
+    #[cfg(not(has_sws_scale_frame))]
+    unsafe fn scale_slices(&mut self, src: &AVFrame, dst: &mut Frame) -> i32 {
+        let ret = ffi::av_frame_get_buffer(dst.as_mut_ptr(), 0);
+        if ret < 0 {
+            return ret;
+        }
+        ffi::sws_scale(
+            self.ctx.as_ptr(),
+            src.data.as_ptr().cast(),
+            src.linesize.as_ptr(),
+            0,
+            src.height,
+            dst.data.as_ptr(),
+            dst.linesize.as_ptr(),
+        )
+    }
+}
+
+impl Drop for Scaler {
+    fn drop(&mut self) {
+        unsafe { ffi::sws_freeContext(self.ctx.as_ptr()) };
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_yuv420p_to_rgb24() {
+        let mut frame = Frame::new().unwrap();
+        frame.format = ffi::AV_PIX_FMT_YUV420P;
+        frame.width = 64;
+        frame.height = 48;
+        frame.pts = 7;
+        assert_eq!(
+            unsafe { ffi::av_frame_get_buffer(frame.as_mut_ptr(), 0) },
+            0
+        );
+        // Limited range white
+        frame.plane_mut(0).unwrap().fill(235);
+        frame.plane_mut(1).unwrap().fill(128);
+        frame.plane_mut(2).unwrap().fill(128);
+
+        let mut scaler = Scaler::new(
+            64,
+            48,
+            ffi::AV_PIX_FMT_YUV420P,
+            32,
+            24,
+            ffi::AV_PIX_FMT_RGB24,
+            ffi::SWS_BILINEAR as i32,
+        )
+        .unwrap();
+        let rgb = scaler.scale_frame(&frame).unwrap();
+        assert_eq!((rgb.width, rgb.height), (32, 24));
+        assert_eq!(rgb.format, ffi::AV_PIX_FMT_RGB24);
+        assert_eq!(rgb.pts, 7);
+        let row = &rgb.plane(0).unwrap()[..32 * 3];
+        assert!(row.iter().all(|&v| v >= 250), "{row:?}");
+
+        frame.width = 32;
+        assert!(scaler.scale_frame(&frame).is_err());
+    }
+}
@@ -0,0 +1,87 @@
+use crate::ffi;
+use alloc::string::String;
+use core::ffi::{c_char, CStr};
+
+pub use crate::ffi::{
+    AVMediaType, AVMEDIA_TYPE_ATTACHMENT, AVMEDIA_TYPE_AUDIO, AVMEDIA_TYPE_DATA,
+    AVMEDIA_TYPE_SUBTITLE, AVMEDIA_TYPE_UNKNOWN, AVMEDIA_TYPE_VIDEO,
+};
+
+/// Name of a media type (`video`, `audio`, `subtitle`, ...), `None` if the
+/// type is unknown.
+pub fn media_type_name(media_type: AVMediaType) -> Option<String> {
+    let name = unsafe { ffi::av_get_media_type_string(media_type) };
+    if name.is_null() {
+        return None;
+    }
+    Some(
+        unsafe { CStr::from_ptr(name) }
+            .to_string_lossy()
+            .into_owned(),
+    )
+}
+
+/// Copy a C string allocated by FFmpeg into a `String` and free the original
+/// with `av_free`. Returns `None` for a null pointer.
+///
+/// # Safety
+/// `ptr` must be null or a nul-terminated string allocated with FFmpeg's
+/// allocator (`av_malloc`, `av_strdup`, ...), which is not used afterwards.
+pub unsafe fn take_av_string(ptr: *mut c_char) -> Option<String> {
+    if ptr.is_null() {
+        return None;
+    }
+    let s = CStr::from_ptr(ptr).to_string_lossy().into_owned();
+    ffi::av_free(ptr.cast());
+    Some(s)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::ptr;
+
+    #[test]
+    fn test_media_type_name() {
+        assert_eq!(
+            media_type_name(AVMEDIA_TYPE_VIDEO).as_deref(),
+            Some("video")
+        );
+        assert_eq!(
+            media_type_name(AVMEDIA_TYPE_SUBTITLE).as_deref(),
+            Some("subtitle")
+        );
+        assert_eq!(media_type_name(AVMEDIA_TYPE_UNKNOWN), None);
+    }
+
+    #[test]
+    fn test_take_av_string() {
+        unsafe {
+            let s = ffi::av_strdup(c"allocated by ffmpeg".as_ptr());
+            assert_eq!(take_av_string(s).as_deref(), Some("allocated by ffmpeg"));
+            assert_eq!(take_av_string(ptr::null_mut()), None);
+        }
+    }
+
+    /// glibc serves large allocations with a mapping of their own, which is
+    /// unmapped as soon as they are freed
+    #[cfg(all(target_os = "linux", target_env = "gnu"))]
+    #[test]
+    fn test_take_av_string_frees() {
+        const LEN: usize = 64 << 20;
+        unsafe {
+            let s = ffi::av_malloc(LEN + 1).cast::<u8>();
+            assert!(!s.is_null());
+            ptr::write_bytes(s, b'a', LEN);
+            *s.add(LEN) = 0;
+            let page_size = libc::sysconf(libc::_SC_PAGESIZE) as usize;
+            let page = (s as usize & !(page_size - 1)) as *mut libc::c_void;
+            let mut residency = 0u8;
+            assert_eq!(libc::mincore(page, page_size, &mut residency), 0);
+
+            assert_eq!(take_av_string(s.cast()).map(|s| s.len()), Some(LEN));
+            // The page is not mapped anymore
+            assert_eq!(libc::mincore(page, page_size, &mut residency), -1);
+        }
+    }
+}
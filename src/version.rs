@@ -0,0 +1,28 @@
+use crate::ffi;
+use alloc::string::String;
+use core::ffi::{c_char, CStr};
+
+/// The `configure` command line libavutil was built with.
+pub fn configuration() -> String {
+    unsafe { to_string(ffi::avutil_configuration()) }
+}
+
+/// License of libavutil, e.g. `GPL version 3 or later`.
+pub fn license() -> String {
+    unsafe { to_string(ffi::avutil_license()) }
+}
+
+unsafe fn to_string(s: *const c_char) -> String {
+    CStr::from_ptr(s).to_string_lossy().into_owned()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_configuration() {
+        assert!(!configuration().is_empty());
+        assert!(license().contains("GPL"));
+    }
+}
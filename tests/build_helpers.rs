@@ -0,0 +1,4 @@
+//! Runs the unit tests of the build script helpers.
+
+#[path = "../build/helpers.rs"]
+mod helpers;
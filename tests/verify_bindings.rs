@@ -0,0 +1,158 @@
+//! Checks that a representative function of every FFmpeg library links and
+//! matches the headers the bindings were generated from.
+
+use rusty_ffmpeg::ffi;
+
+#[test]
+fn test_libraries_link() {
+    let versions = unsafe {
+        [
+            (
+                "libavcodec",
+                ffi::avcodec_version(),
+                ffi::LIBAVCODEC_VERSION_MAJOR,
+            ),
+            (
+                "libavdevice",
+                ffi::avdevice_version(),
+                ffi::LIBAVDEVICE_VERSION_MAJOR,
+            ),
+            (
+                "libavfilter",
+                ffi::avfilter_version(),
+                ffi::LIBAVFILTER_VERSION_MAJOR,
+            ),
+            (
+                "libavformat",
+                ffi::avformat_version(),
+                ffi::LIBAVFORMAT_VERSION_MAJOR,
+            ),
+            (
+                "libavutil",
+                ffi::avutil_version(),
+                ffi::LIBAVUTIL_VERSION_MAJOR,
+            ),
+            (
+                "libswresample",
+                ffi::swresample_version(),
+                ffi::LIBSWRESAMPLE_VERSION_MAJOR,
+            ),
+            (
+                "libswscale",
+                ffi::swscale_version(),
+                ffi::LIBSWSCALE_VERSION_MAJOR,
+            ),
+        ]
+    };
+    for (lib, version, header_major) in versions {
+        assert_eq!(
+            version >> 16,
+            header_major,
+            "{lib} doesn't match its headers"
+        );
+    }
+}
+
+#[test]
+fn test_swscale_links() {
+    let ctx = unsafe {
+        ffi::sws_getContext(
+            16,
+            16,
+            ffi::AV_PIX_FMT_YUV420P,
+            16,
+            16,
+            ffi::AV_PIX_FMT_RGB24,
+            ffi::SWS_BILINEAR as i32,
+            std::ptr::null_mut(),
+            std::ptr::null_mut(),
+            std::ptr::null(),
+        )
+    };
+    assert!(!ctx.is_null());
+    unsafe { ffi::sws_freeContext(ctx) };
+}
+
+#[cfg(not(feature = "prebuilt_bindings"))]
+#[test]
+fn test_core_bindings() {
+    let binding = include_str!(concat!(env!("OUT_DIR"), "/binding.rs"));
+    assert!(binding.contains("pub fn avcodec_send_frame("));
+    assert!(binding.contains("pub fn sws_scale("));
+    // Bound for libswscale 6 and later, see build.rs
+    assert_eq!(
+        binding.contains("pub fn sws_scale_frame("),
+        cfg!(has_sws_scale_frame)
+    );
+    // Headers outside of the core set are only bound on demand
+    assert_eq!(
+        binding.contains("pub fn av_lfg_init("),
+        cfg!(feature = "full-bindings")
+    );
+    assert_eq!(
+        binding.contains("pub fn av_tree_insert("),
+        cfg!(feature = "full-bindings")
+    );
+    assert_eq!(
+        binding.contains("pub fn av_murmur3_init("),
+        cfg!(feature = "hash")
+    );
+    assert_eq!(
+        binding.contains("pub fn av_aes_alloc("),
+        cfg!(feature = "crypto")
+    );
+}
+
+/// The bindings checked in for the target are used as is, without running bindgen
+#[cfg(feature = "prebuilt_bindings")]
+#[test]
+fn test_prebuilt_bindings() {
+    let binding = include_str!(concat!(env!("OUT_DIR"), "/binding.rs"));
+    let prebuilt_binding_path = format!(
+        "{}/src/prebuilt/binding_{}.rs",
+        env!("CARGO_MANIFEST_DIR"),
+        env!("RUSTY_FFMPEG_TARGET"),
+    );
+    let prebuilt_binding = std::fs::read_to_string(&prebuilt_binding_path).unwrap_or_else(|e| {
+        panic!("no prebuilt bindings for the target, {prebuilt_binding_path}: {e}")
+    });
+    assert_eq!(env!("RUSTY_FFMPEG_BINDINGS"), "prebuilt");
+    assert!(
+        binding == prebuilt_binding,
+        "{prebuilt_binding_path} was not copied as is"
+    );
+}
+
+/// `long double` is a 128-bit float on aarch64, which bindgen has no type for
+#[cfg(all(target_arch = "aarch64", not(feature = "prebuilt_bindings")))]
+#[test]
+fn test_aarch64_long_double_bindings() {
+    let binding = include_str!(concat!(env!("OUT_DIR"), "/binding.rs"));
+    assert!(!binding.contains("pub type __float128"));
+    assert!(!binding.contains("pub type _Float128"));
+    // max_align_t is bound as a blob of its size and alignment
+    assert!(!binding.contains("__clang_max_align_nonce2"));
+    if binding.contains("pub struct max_align_t") {
+        assert!(binding.contains("pub struct max_align_t {\n    pub _bindgen_opaque_blob"));
+    }
+}
+
+#[cfg(feature = "crypto")]
+#[test]
+fn test_crypto_bindings() {
+    unsafe {
+        let aes = ffi::av_aes_alloc();
+        assert!(!aes.is_null());
+        assert_eq!(ffi::av_aes_init(aes, [0u8; 16].as_ptr(), 128, 0), 0);
+        ffi::av_free(aes.cast());
+    }
+}
+
+#[cfg(feature = "hash")]
+#[test]
+fn test_hash_bindings() {
+    let mut digest = [0u8; 16];
+    unsafe { ffi::av_md5_sum(digest.as_mut_ptr(), b"".as_ptr(), 0) };
+    // MD5 of an empty input
+    assert_eq!(digest[..4], [0xd4, 0x1d, 0x8c, 0xd9]);
+}